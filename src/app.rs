@@ -1,75 +1,841 @@
-use crate::entities::ParsedTest;
+use crate::entities::{Collection, CollectionError, Outcome, ParsedTest, TestResult};
+use crate::expression::{self, Expression};
+use crate::filter::{self, FilterMatch, FilterMode};
+use crate::fixtures::{FixtureIndex, ResolvedFixture};
+use crate::formats;
+use crate::junit;
+use crate::runner::{RunStatus, TestRun};
+use crate::tree::Tree;
 use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// time to wait for more saves before tests are run again
+const RERUN_DELAY: Duration = Duration::from_millis(300);
 
 pub enum InputMode {
     TestScrolling,
     OutputScrolling,
     FilterEditing,
     ErrorMessage,
+    /// fixtures of the test under the cursor are shown instead of the output
+    FixtureScrolling,
+    /// the Errors pane is expanded and its cursor moves
+    CollectionErrorScrolling,
 }
 
 pub struct App {
     pub input: String,
     pub input_mode: InputMode,
+    pub filter_mode: FilterMode,
     pub test_stdout: String,
+    pub test_run: Option<TestRun>,
+    /// results of the last run of each test, keyed by full_path
+    pub results: HashMap<String, TestResult>,
     pub stdout_cursor: usize,
     pub tests: Vec<ParsedTest>,
+    pub collection_errors: Vec<CollectionError>,
+    pub collection_error_cursor: usize,
+    pub fixtures: FixtureIndex,
+    /// row in the fixtures pane
+    pub fixture_cursor: usize,
     pub filtered_tests_count: usize,
+    /// indices of the tests matching the filter in list order, see update_filtered_test_count
+    filtered: Vec<usize>,
+    /// rows in the tests list, differs from filtered_tests_count in tree mode
+    pub rows_count: usize,
     pub test_cursor: usize,
+    pub tree_mode: bool,
+    /// node ids of collapsed tree nodes
+    pub collapsed: HashSet<String>,
+    /// full_paths of tests marked by the user, actions apply to them when it isn't empty
+    pub selection: HashSet<String>,
+    /// unconditionally skipped tests are left out of the list
+    pub hide_skipped: bool,
     pub loading_lock: bool,
     pub error_message: String,
+    /// short feedback on the last action, cleared on the next key press
+    pub notice: String,
+    /// changed tests are run again when their files are saved
+    pub auto_rerun: bool,
+    /// node ids waiting for the saves to settle and for the current run to finish
+    pub pending_rerun: Vec<String>,
+    rerun_at: Option<Instant>,
+    /// options every pytest run gets, the `--rootdir` and `-c` pytexp was started with
+    pub pytest_options: Vec<String>,
+    /// paths and node ids pytexp was started with, `-k` and `-m` runs are limited to them
+    pub pytest_args: Vec<String>,
 }
 
 impl App {
     pub fn new(tests: Vec<ParsedTest>) -> App {
-        App {
+        let mut app = App {
             input: String::new(),
             input_mode: InputMode::TestScrolling,
+            filter_mode: FilterMode::Exact,
             test_stdout: String::new(),
+            test_run: None,
+            results: HashMap::new(),
             stdout_cursor: 0,
             tests,
+            collection_errors: vec![],
+            collection_error_cursor: 0,
+            fixtures: FixtureIndex::default(),
+            fixture_cursor: 0,
             filtered_tests_count: 0,
+            filtered: vec![],
+            rows_count: 0,
             test_cursor: 0,
+            tree_mode: false,
+            collapsed: HashSet::new(),
+            selection: HashSet::new(),
+            hide_skipped: false,
             loading_lock: false,
             error_message: String::new(),
-        }
+            notice: String::new(),
+            auto_rerun: false,
+            pending_rerun: vec![],
+            rerun_at: None,
+            pytest_options: vec![],
+            pytest_args: vec![],
+        };
+        app.update_filtered_test_count();
+        app
     }
     pub fn load_filters_from_app(&self) -> Vec<String> {
         self.input.trim().split(' ').map(String::from).collect()
     }
 
     pub fn is_accure_all_filters(filters: &[String], t: &str) -> bool {
-        filters.to_owned().iter().cloned().all(|f| t.contains(&f))
+        filters.iter().all(|f| t.contains(f.as_str()))
+    }
+
+    pub fn filtered_tests(&self) -> impl Iterator<Item = &ParsedTest> {
+        self.filtered_tests_indexed().map(|(_, t)| t)
+    }
+
+    /// Tests matching the filter with their indices
+    fn filtered_tests_indexed(&self) -> impl Iterator<Item = (usize, &ParsedTest)> {
+        self.filtered.iter().map(|&i| (i, &self.tests[i]))
+    }
+
+    /// Indices of the tests matching the filter, fuzzy matches are ranked by score
+    fn filter_tests(&self) -> Vec<usize> {
+        let filtered: Vec<(usize, &ParsedTest)> = match self.filter_mode {
+            FilterMode::Exact => {
+                let filters = self.load_filters_from_app();
+                self.tests
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, t)| App::is_accure_all_filters(&filters, &t.full_path))
+                    .collect()
+            }
+            FilterMode::Fuzzy => {
+                let mut scored: Vec<(i64, usize, &ParsedTest)> = self
+                    .tests
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, t)| {
+                        filter::fuzzy_match(&self.input, &t.full_path).map(|m| (m.score, i, t))
+                    })
+                    .collect();
+                // sorting is stable, equally scored tests keep the file order
+                scored.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+                scored.into_iter().map(|(_, i, t)| (i, t)).collect()
+            }
+            FilterMode::Keyword | FilterMode::Marker if self.input.trim().is_empty() => {
+                self.tests.iter().enumerate().collect()
+            }
+            FilterMode::Keyword | FilterMode::Marker => match Expression::parse(&self.input) {
+                Ok(expression) => self
+                    .tests
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, t)| match self.filter_mode {
+                        FilterMode::Marker => expression::marker_matches(&expression, &t.markers),
+                        _ => expression::keyword_matches(&expression, &keyword_names(t)),
+                    })
+                    .collect(),
+                Err(_) => vec![],
+            },
+        };
+        filtered
+            .into_iter()
+            .filter(|(_, t)| !self.hide_skipped || !t.is_skipped())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Matched chars of the text, for highlighting
+    pub fn filter_match(&self, text: &str) -> Option<FilterMatch> {
+        match self.filter_mode {
+            FilterMode::Exact => filter::exact_match(&self.load_filters_from_app(), text),
+            FilterMode::Fuzzy => filter::fuzzy_match(&self.input, text),
+            FilterMode::Keyword | FilterMode::Marker => None,
+        }
+    }
+
+    /// Why the filter doesn't match anything, e.g. a syntax error in the `-k` expression
+    pub fn filter_error(&self) -> Option<String> {
+        self.filter_mode.pytest_option()?;
+        Expression::parse(&self.input).err().map(|e| e.to_string())
+    }
+
+    pub fn toggle_filter_mode(&mut self) {
+        self.filter_mode = self.filter_mode.next();
+        self.update_filtered_test_count();
     }
 
+    /// Tree of the filtered tests
+    pub fn tree(&self) -> Tree {
+        Tree::build(self.filtered_tests_indexed())
+    }
+
+    /// Tree and the index of the node under the cursor
+    fn cursor_node(&self) -> Option<(Tree, usize)> {
+        let tree = self.tree();
+        let node = *tree.visible_rows(&self.collapsed).get(self.test_cursor)?;
+        Some((tree, node))
+    }
+
+    /// Test under the cursor, in tree mode the first test of the node under the cursor
     pub fn find_selected_test(&self) -> Option<ParsedTest> {
-        let filters = self.load_filters_from_app();
-        self.tests
-            .iter()
-            .filter(|t| App::is_accure_all_filters(&filters, &t.full_path))
-            .nth(self.test_cursor)
-            .cloned()
+        if !self.tree_mode {
+            return self.filtered_tests().nth(self.test_cursor).cloned();
+        }
+        let (tree, node) = self.cursor_node()?;
+        let node = &tree.nodes[node];
+        let index = node.test.or_else(|| node.tests.first().copied())?;
+        Some(self.tests[index].clone())
+    }
+
+    /// full_paths of the test under the cursor, or all tests of the tree node under it
+    fn cursor_full_paths(&self) -> Vec<String> {
+        match self.tree_mode {
+            true => self
+                .cursor_node()
+                .map(|(tree, node)| {
+                    let node = &tree.nodes[node];
+                    let tests = node.tests.iter().chain(node.test.iter());
+                    tests.map(|i| self.tests[*i].full_path.clone()).collect()
+                })
+                .unwrap_or_default(),
+            false => self
+                .find_selected_test()
+                .map(|t| vec![t.full_path])
+                .unwrap_or_default(),
+        }
     }
 
+    /// Filters the tests again, has to follow every change of the tests, the input or
+    /// the filter options
     pub fn update_filtered_test_count(&mut self) {
-        let filters = self.load_filters_from_app();
-        self.filtered_tests_count = self
+        self.filtered = self.filter_tests();
+        self.filtered_tests_count = self.filtered.len();
+        self.update_rows_count();
+    }
+
+    fn update_rows_count(&mut self) {
+        self.rows_count = match self.tree_mode {
+            true => self.tree().visible_rows(&self.collapsed).len(),
+            false => self.filtered_tests_count,
+        };
+        self.test_cursor = min(self.test_cursor, self.rows_count.saturating_sub(1));
+    }
+
+    pub fn toggle_hide_skipped(&mut self) {
+        self.hide_skipped = !self.hide_skipped;
+        self.update_filtered_test_count();
+        self.test_cursor = min(self.test_cursor, self.rows_count.saturating_sub(1));
+    }
+
+    /// Switches between the flat list and the tree, keeping the cursor on the same test
+    pub fn toggle_tree_mode(&mut self) {
+        let current = self.find_selected_test().map(|t| t.full_path);
+        self.tree_mode = !self.tree_mode;
+        self.update_rows_count();
+        if let Some(current) = current {
+            self.test_cursor = self.row_of(&current).unwrap_or(0);
+        }
+    }
+
+    /// Row of the test or tree node in the list
+    fn row_of(&self, full_path: &str) -> Option<usize> {
+        match self.tree_mode {
+            true => {
+                let tree = self.tree();
+                let rows = tree.visible_rows(&self.collapsed);
+                rows.iter()
+                    .position(|n| tree.nodes[*n].node_id == full_path)
+            }
+            false => self.filtered_tests().position(|t| t.full_path == full_path),
+        }
+    }
+
+    /// Replaces the tests and errors of the re-collected modules, modules stay
+    /// in path order and the cursor stays on the same test
+    pub fn update_modules(&mut self, modules: &[String], collection: Collection) {
+        self.fixtures.update(collection.fixtures);
+        if modules.is_empty() {
+            return;
+        }
+        let current = self.find_selected_test().map(|t| t.full_path);
+        let updated = |path: &str| modules.iter().any(|m| m == path);
+        let previous: HashMap<String, u64> = self
             .tests
             .iter()
-            .filter(|t| App::is_accure_all_filters(&filters, &t.full_path))
-            .count();
-        self.test_cursor = min(
-            self.test_cursor,
-            self.filtered_tests_count.saturating_sub(1),
+            .filter(|t| updated(module_path(&t.full_path)))
+            .map(|t| (t.full_path.clone(), t.source_hash))
+            .collect();
+        self.tests.retain(|t| !updated(module_path(&t.full_path)));
+        if self.auto_rerun {
+            let changed: Vec<String> = collection
+                .tests
+                .iter()
+                .filter(|t| previous.get(&t.full_path) != Some(&t.source_hash))
+                .map(|t| t.full_path.clone())
+                .collect();
+            // a changed helper or fixture affects tests which haven't changed
+            let rerun = match changed.is_empty() {
+                true => collection
+                    .tests
+                    .iter()
+                    .map(|t| t.full_path.clone())
+                    .collect(),
+                false => changed,
+            };
+            self.schedule_rerun(rerun);
+        }
+        for test in collection.tests {
+            let module = Path::new(module_path(&test.full_path));
+            let position = self
+                .tests
+                .partition_point(|t| Path::new(module_path(&t.full_path)) <= module);
+            self.tests.insert(position, test);
+        }
+        self.collection_errors.retain(|e| !updated(&e.path));
+        self.collection_errors.extend(collection.errors);
+        self.collection_errors
+            .sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+        self.collection_error_cursor = min(
+            self.collection_error_cursor,
+            self.collection_errors.len().saturating_sub(1),
         );
+        if self.collection_errors.is_empty() {
+            if let InputMode::CollectionErrorScrolling = self.input_mode {
+                self.input_mode = InputMode::TestScrolling;
+            }
+        }
+        self.update_filtered_test_count();
+        if let Some(row) = current.and_then(|current| self.row_of(&current)) {
+            self.test_cursor = row;
+        }
+        self.notice = "collection updated".to_string();
+    }
+
+    /// Folds or unfolds the tree node under the cursor
+    pub fn toggle_fold(&mut self) {
+        let Some((tree, node)) = self.cursor_node() else {
+            return;
+        };
+        let node = &tree.nodes[node];
+        if !node.is_leaf() && !self.collapsed.remove(&node.node_id) {
+            self.collapsed.insert(node.node_id.clone());
+        }
+        self.update_rows_count();
+    }
+
+    /// Folds every node, or unfolds all of them when everything is folded already
+    pub fn toggle_fold_all(&mut self) {
+        let tree = self.tree();
+        let inner: Vec<String> = tree.inner_node_ids().map(String::from).collect();
+        match inner.iter().all(|id| self.collapsed.contains(id)) {
+            true => self.collapsed.clear(),
+            false => {
+                self.collapsed.extend(inner);
+                self.test_cursor = 0;
+            }
+        }
+        self.update_rows_count();
+    }
+
+    pub fn toggle_selection(&mut self) {
+        let full_paths = self.cursor_full_paths();
+        if full_paths.iter().all(|p| self.selection.contains(p)) {
+            for full_path in &full_paths {
+                self.selection.remove(full_path);
+            }
+        } else {
+            self.selection.extend(full_paths);
+        }
+    }
+
+    pub fn select_filtered(&mut self) {
+        let filtered: Vec<String> = self.filtered_tests().map(|t| t.full_path.clone()).collect();
+        self.selection.extend(filtered);
+    }
+
+    /// Inverts the selection of the filtered tests, hidden ones are left as they are
+    pub fn invert_selection(&mut self) {
+        let filtered: Vec<String> = self.filtered_tests().map(|t| t.full_path.clone()).collect();
+        for full_path in filtered {
+            if !self.selection.remove(&full_path) {
+                self.selection.insert(full_path);
+            }
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    /// Node ids actions apply to: the selection in list order or the test under the cursor
+    pub fn target_node_ids(&self) -> Vec<String> {
+        if self.selection.is_empty() && self.tree_mode {
+            return self
+                .cursor_node()
+                .map(|(tree, node)| vec![tree.nodes[node].node_id.clone()])
+                .unwrap_or_default();
+        }
+        if self.selection.is_empty() {
+            return self
+                .find_selected_test()
+                .map(|t| vec![t.full_path])
+                .unwrap_or_default();
+        }
+        self.tests
+            .iter()
+            .filter(|t| self.selection.contains(&t.full_path))
+            .map(|t| t.full_path.clone())
+            .collect()
+    }
+
+    pub fn run_targets(&mut self) {
+        let node_ids = self.target_node_ids();
+        self.start_tests(node_ids);
+    }
+
+    /// Runs the tests in one pytest process, a run which is still going on is stopped
+    pub fn start_tests(&mut self, node_ids: Vec<String>) {
+        if !node_ids.is_empty() {
+            self.start_run(prune_node_ids(node_ids), vec![]);
+        }
+    }
+
+    fn start_run(&mut self, node_ids: Vec<String>, options: Vec<String>) {
+        self.test_run = None;
+        self.test_stdout = String::new();
+        self.stdout_cursor = 0;
+        let options = self.pytest_options.iter().cloned().chain(options).collect();
+        match TestRun::start(node_ids, options) {
+            Ok(test_run) => self.test_run = Some(test_run),
+            Err(err) => self.set_error(err),
+        }
+    }
+
+    /// Collects output of the running test, returns true when something changed
+    pub fn poll_test_run(&mut self) -> bool {
+        let Some(test_run) = self.test_run.as_mut() else {
+            return false;
+        };
+        let output_len = self.test_stdout.len();
+        if let RunStatus::Finished(status) = test_run.poll(&mut self.test_stdout) {
+            if test_run.is_cancelling() {
+                self.test_stdout
+                    .push_str(&format!("\ncancelled ({status})\n"));
+            }
+            if let Ok(cases) = test_run.report() {
+                let full_paths = self.tests.iter().map(|t| t.full_path.as_str());
+                self.results
+                    .extend(junit::results_by_path(&cases, full_paths));
+            }
+            self.test_run = None;
+            return true;
+        }
+        self.test_stdout.len() != output_len
+    }
+
+    /// Fixtures pytest would set up for the test under the cursor
+    pub fn fixture_chain(&self) -> Vec<ResolvedFixture<'_>> {
+        match self.find_selected_test() {
+            Some(test) => self.fixtures.resolve(&test),
+            None => vec![],
+        }
+    }
+
+    pub fn show_fixtures(&mut self) {
+        self.fixture_cursor = 0;
+        self.input_mode = InputMode::FixtureScrolling;
+    }
+
+    pub fn show_collection_errors(&mut self) {
+        if !self.collection_errors.is_empty() {
+            self.input_mode = InputMode::CollectionErrorScrolling;
+        }
+    }
+
+    /// File and line of the error under the cursor of the expanded Errors pane
+    pub fn collection_error_under_cursor(&self) -> Option<(String, usize)> {
+        let error = self.collection_errors.get(self.collection_error_cursor)?;
+        Some((error.path.clone(), error.line.unwrap_or(1)))
+    }
+
+    /// File and line of the fixture under the cursor of the fixtures pane
+    pub fn fixture_under_cursor(&self) -> Option<(String, usize)> {
+        let chain = self.fixture_chain();
+        let definition = chain.get(self.fixture_cursor)?.definition?;
+        Some((definition.path.clone(), definition.fixture.row_location))
+    }
+
+    pub fn toggle_auto_rerun(&mut self) {
+        self.auto_rerun = !self.auto_rerun;
+        self.pending_rerun.clear();
+        self.rerun_at = None;
+        self.notice = match self.auto_rerun {
+            true => "rerun on save on".to_string(),
+            false => "rerun on save off".to_string(),
+        };
+    }
+
+    /// Runs the tests once the saves settle, rapid saves are run together
+    fn schedule_rerun(&mut self, node_ids: Vec<String>) {
+        if node_ids.is_empty() {
+            return;
+        }
+        for node_id in node_ids {
+            if !self.pending_rerun.contains(&node_id) {
+                self.pending_rerun.push(node_id);
+            }
+        }
+        self.rerun_at = Some(Instant::now() + RERUN_DELAY);
+    }
+
+    /// Starts the pending rerun when it's due and no other run is going on,
+    /// returns true when it was started
+    pub fn poll_rerun(&mut self) -> bool {
+        match self.rerun_at {
+            Some(at) if at <= Instant::now() && self.test_run.is_none() => {
+                self.rerun_at = None;
+                let node_ids = std::mem::take(&mut self.pending_rerun);
+                self.start_tests(node_ids);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the filtered tests, `-k` and `-m` expressions are passed to pytest as they are
+    pub fn run_filtered_tests(&mut self) {
+        if let Some(option) = self.filter_mode.pytest_option() {
+            if !self.input.trim().is_empty() {
+                let options = vec![option.to_string(), self.input.trim().to_string()];
+                return self.start_run(self.pytest_args.clone(), options);
+            }
+        }
+        let node_ids = self.filtered_tests().map(|t| t.full_path.clone()).collect();
+        self.start_tests(node_ids);
+    }
+
+    /// Runs the module the test under the cursor belongs to
+    pub fn run_file_under_cursor(&mut self) {
+        if let Some(test) = self.find_selected_test() {
+            let file = test.full_path.split("::").next().unwrap_or_default();
+            self.start_tests(vec![file.to_string()]);
+        }
+    }
+
+    /// Runs the class the test under the cursor belongs to, or the class itself.
+    /// Tests of nested classes run the innermost one
+    pub fn run_class_under_cursor(&mut self) {
+        let Some(test) = self.find_selected_test() else {
+            return;
+        };
+        if self.is_class_row(&test) {
+            return self.start_tests(vec![test.full_path]);
+        }
+        // the parametrize id may contain `::` itself
+        let class_id = match formats::node_path(&test).rsplit_once("::") {
+            Some((class_id, _)) if class_id.contains("::") => class_id.to_string(),
+            _ => {
+                let name = test.test_name;
+                return self.set_error(anyhow::anyhow!("{name} isn't defined in a class"));
+            }
+        };
+        self.start_tests(vec![class_id]);
+    }
+
+    fn is_class_row(&self, test: &ParsedTest) -> bool {
+        let prefix = format!("{}::", test.full_path);
+        self.tests.iter().any(|t| t.full_path.starts_with(&prefix))
+    }
+
+    /// Class rows and unresolved parametrized tests summarize the tests inside of them
+    pub fn outcome(&self, test: &ParsedTest) -> Outcome {
+        if let Some(result) = self.results.get(&test.full_path) {
+            return result.outcome;
+        }
+        let path = test.full_path.as_str();
+        self.results
+            .iter()
+            .filter(|(p, _)| {
+                p.strip_prefix(path).map_or(false, |rest| {
+                    rest.starts_with("::") || rest.starts_with('[')
+                })
+            })
+            .map(|(_, r)| r.outcome)
+            .max()
+            .unwrap_or(Outcome::NotRun)
+    }
+
+    pub fn cancel_test(&mut self) {
+        if let Some(test_run) = self.test_run.as_mut() {
+            test_run.cancel();
+        }
     }
 
     pub fn set_error(&mut self, err: anyhow::Error) {
         self.error_message = err.to_string();
         self.input_mode = InputMode::ErrorMessage;
     }
-    pub fn clean_error(&mut self){
+    pub fn clean_error(&mut self) {
         self.input_mode = InputMode::TestScrolling;
         self.error_message = String::new();
     }
 }
+
+/// Path of the module the test was collected from
+fn module_path(full_path: &str) -> &str {
+    full_path.split("::").next().unwrap_or_default()
+}
+
+/// Names `-k` expressions are matched against like pytest's: the ones of the directories,
+/// the module, the classes and the function, with its parametrize id too, and the markers
+fn keyword_names(test: &ParsedTest) -> Vec<&str> {
+    let path = formats::node_path(test);
+    let mut segments = path.split("::");
+    let mut names: Vec<&str> = segments.next().unwrap_or_default().split('/').collect();
+    names.extend(segments);
+    if path.len() < test.full_path.len() {
+        // e.g. `test_get[a::b]`
+        let function = names.last().map_or(0, |name| name.len());
+        names.push(&test.full_path[path.len() - function..]);
+    }
+    names.extend(test.markers.iter().map(String::as_str));
+    names
+}
+
+/// Drops node ids which are already run as a part of a module, class or unresolved parametrized test
+fn prune_node_ids(node_ids: Vec<String>) -> Vec<String> {
+    let covers = |parent: &str, node_id: &str| {
+        node_id.strip_prefix(parent).map_or(false, |rest| {
+            rest.starts_with("::") || rest.starts_with('[')
+        })
+    };
+    node_ids
+        .iter()
+        .filter(|node_id| !node_ids.iter().any(|parent| covers(parent, node_id)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prune_node_ids, App, InputMode};
+    use crate::entities::{Collection, CollectionError, Parametrization, ParsedTest};
+    use crate::filter::FilterMode;
+
+    #[test]
+    fn test_selection() {
+        let tests = ["test_a", "test_b", "test_c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ParsedTest::new(name.to_string(), i + 1, "test_x.py"))
+            .collect();
+        let mut app = App::new(tests);
+        app.test_cursor = 1;
+        assert_eq!(app.target_node_ids(), vec!["test_x.py::test_b"]);
+
+        app.toggle_selection();
+        app.input = "_c".to_string();
+        app.update_filtered_test_count();
+        app.select_filtered();
+        assert_eq!(
+            app.target_node_ids(),
+            vec!["test_x.py::test_b", "test_x.py::test_c"]
+        );
+
+        // only the filtered tests are inverted
+        app.selection.remove("test_x.py::test_c");
+        app.input = "test_".to_string();
+        app.update_filtered_test_count();
+        app.invert_selection();
+        assert_eq!(
+            app.target_node_ids(),
+            vec!["test_x.py::test_a", "test_x.py::test_c"]
+        );
+    }
+
+    #[test]
+    fn test_keyword_filter() {
+        let tests = [
+            ("test_get", "tests/unit/test_api.py"),
+            ("test_get", "tests/unit/test_client.py"),
+            ("TestUnit::test_get", "tests/api/test_client.py"),
+            ("test_post[a::b]", "tests/unit/test_client.py"),
+        ]
+        .iter()
+        .map(|(name, path)| {
+            let mut test = ParsedTest::new(name.to_string(), 1, path);
+            if let Some((_, id)) = name.split_once('[') {
+                test.parametrization = Some(Parametrization::Id(id.replace(']', "")));
+            }
+            test
+        })
+        .collect();
+        let mut app = App::new(tests);
+        app.filter_mode = FilterMode::Keyword;
+        let filtered = |app: &mut App, input: &str| {
+            app.input = input.to_string();
+            app.update_filtered_test_count();
+            let full_paths: Vec<String> =
+                app.filtered_tests().map(|t| t.full_path.clone()).collect();
+            full_paths
+        };
+
+        assert_eq!(
+            filtered(&mut app, "unit and not api"),
+            vec![
+                "tests/unit/test_client.py::test_get",
+                "tests/unit/test_client.py::test_post[a::b]",
+            ]
+        );
+        // names are matched one by one, the parametrize id is a part of the function's
+        assert!(filtered(&mut app, "unit/test_client").is_empty());
+        assert_eq!(
+            filtered(&mut app, "post[a::b]"),
+            vec!["tests/unit/test_client.py::test_post[a::b]"]
+        );
+    }
+
+    #[test]
+    fn test_update_modules() {
+        let test = |name: &str, path: &str| ParsedTest::new(name.to_string(), 1, path);
+        let mut app = App::new(vec![
+            test("test_a", "a/test_x.py"),
+            test("test_b", "b/test_y.py"),
+            test("test_c", "c/test_z.py"),
+        ]);
+        app.update_filtered_test_count();
+        app.test_cursor = 2;
+
+        let collection = Collection {
+            tests: vec![test("test_b", "b/test_y.py"), test("test_d", "b/test_y.py")],
+            ..Default::default()
+        };
+        app.update_modules(&["b/test_y.py".to_string()], collection);
+        let full_paths: Vec<&str> = app.tests.iter().map(|t| t.full_path.as_str()).collect();
+        assert_eq!(
+            full_paths,
+            vec![
+                "a/test_x.py::test_a",
+                "b/test_y.py::test_b",
+                "b/test_y.py::test_d",
+                "c/test_z.py::test_c"
+            ]
+        );
+        // the cursor follows the test it was on
+        assert_eq!(app.test_cursor, 3);
+        assert_eq!(app.notice, "collection updated");
+
+        app.update_modules(&["a/test_x.py".to_string()], Collection::default());
+        assert_eq!(app.tests.len(), 3);
+        assert_eq!(app.test_cursor, 2);
+    }
+
+    #[test]
+    fn test_collection_errors_pane() {
+        let error = |path: &str| CollectionError {
+            path: path.to_string(),
+            line: Some(3),
+            column: None,
+            message: "invalid syntax".to_string(),
+        };
+        let mut app = App::new(vec![]);
+        app.show_collection_errors();
+        assert!(matches!(app.input_mode, InputMode::TestScrolling));
+
+        app.collection_errors = (0..8).map(|i| error(&format!("test_{i}.py"))).collect();
+        app.show_collection_errors();
+        assert!(matches!(
+            app.input_mode,
+            InputMode::CollectionErrorScrolling
+        ));
+        app.collection_error_cursor = 7;
+        assert_eq!(
+            app.collection_error_under_cursor(),
+            Some(("test_7.py".to_string(), 3))
+        );
+
+        // fixed modules take their errors along, the cursor stays in the list
+        let modules: Vec<String> = (2..8).map(|i| format!("test_{i}.py")).collect();
+        app.update_modules(&modules, Collection::default());
+        assert_eq!(app.collection_error_cursor, 1);
+        let modules = ["test_0.py".to_string(), "test_1.py".to_string()];
+        app.update_modules(&modules, Collection::default());
+        assert!(matches!(app.input_mode, InputMode::TestScrolling));
+    }
+
+    #[test]
+    fn test_rerun_of_changed_tests() {
+        let test = |name: &str, hash: u64| {
+            let mut test = ParsedTest::new(name.to_string(), 1, "test_x.py");
+            test.source_hash = hash;
+            test
+        };
+        let mut app = App::new(vec![test("test_a", 1), test("test_b", 2)]);
+        app.toggle_auto_rerun();
+        let modules = ["test_x.py".to_string()];
+
+        let collection = Collection {
+            tests: vec![test("test_a", 1), test("test_b", 3), test("test_c", 4)],
+            ..Default::default()
+        };
+        app.update_modules(&modules, collection);
+        assert_eq!(
+            app.pending_rerun,
+            vec!["test_x.py::test_b", "test_x.py::test_c"]
+        );
+
+        // nothing changed in the tests themselves, e.g. a fixture did
+        app.pending_rerun.clear();
+        let collection = Collection {
+            tests: vec![test("test_a", 1), test("test_b", 3), test("test_c", 4)],
+            ..Default::default()
+        };
+        app.update_modules(&modules, collection);
+        assert_eq!(app.pending_rerun.len(), 3);
+        // the saves haven't settled yet
+        assert!(!app.poll_rerun());
+    }
+
+    #[test]
+    fn test_prune_node_ids() {
+        let node_ids = vec![
+            "test_a.py::TestA".to_string(),
+            "test_a.py::TestA::test_one[1]".to_string(),
+            "test_a.py::TestAB::test_two".to_string(),
+            "test_b.py::test_three".to_string(),
+            "test_b.py::test_three[x]".to_string(),
+        ];
+
+        assert_eq!(
+            prune_node_ids(node_ids),
+            vec![
+                "test_a.py::TestA",
+                "test_a.py::TestAB::test_two",
+                "test_b.py::test_three"
+            ]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::entities::ParsedModule;
+use crate::parser;
+
+/// Directory of the cache under the rootdir, next to the one of pytest
+const CACHE_DIR: &str = ".pytest_cache/pytexp";
+const CACHE_FILE: &str = "modules.json";
+
+/// Parsed modules of the previous run. Parsed modules are cached rather than
+/// the tests, because tests of a module depend on the classes of other modules.
+/// A module is reused when its mtime and size are unchanged, or when its
+/// content hash is, e.g. after a checkout which touched the file
+#[derive(Default)]
+pub struct ModuleCache {
+    /// None when the cache isn't stored
+    file: Option<PathBuf>,
+    /// pytexp version and collection config the modules were parsed with
+    key: String,
+    entries: HashMap<PathBuf, Entry>,
+    /// modules of this run, they replace their entries when the cache is saved
+    fresh: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    mtime: (u64, u32),
+    size: u64,
+    hash: u64,
+    module: ParsedModule,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    key: String,
+    modules: Vec<(PathBuf, Entry)>,
+}
+
+impl ModuleCache {
+    /// Cache of the rootdir. It's empty when it was written by another version
+    /// of pytexp or with another config, or when `clear` is set
+    pub fn load(config: &Config, clear: bool) -> Self {
+        let file = config.rootdir.join(CACHE_DIR).join(CACHE_FILE);
+        let key = cache_key(config);
+        let entries = match clear {
+            true => HashMap::new(),
+            false => read_entries(&file, &key).unwrap_or_default(),
+        };
+        ModuleCache {
+            file: Some(file),
+            key,
+            entries,
+            ..Default::default()
+        }
+    }
+
+    /// Cache which parses every module and isn't stored
+    pub fn disabled() -> Self {
+        ModuleCache::default()
+    }
+
+    /// Parsed module from the cache, or parsed again when the file has changed
+    pub fn parse_module(&self, path: &Path, config: &Config) -> Result<ParsedModule> {
+        if self.file.is_none() {
+            return parser::parse_module(path, &config.target);
+        }
+        let absolute = std::env::current_dir()?.join(path);
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        let mtime = (mtime.as_secs(), mtime.subsec_nanos());
+        let size = metadata.len();
+        let cached = self.entries.get(&absolute);
+        let mut entry = match cached {
+            Some(entry) if entry.mtime == mtime && entry.size == size => entry.clone(),
+            _ => {
+                let contents = fs::read_to_string(path)?;
+                let hash = content_hash(&contents);
+                let module = match cached {
+                    Some(entry) if entry.size == size && entry.hash == hash => entry.module.clone(),
+                    _ => parser::parse_module_source(
+                        &contents,
+                        parser::utf8_path(path)?,
+                        &config.target,
+                    )?,
+                };
+                Entry {
+                    mtime,
+                    size,
+                    hash,
+                    module,
+                }
+            }
+        };
+        // paths are relative to the invocation dir, which may differ from the cached run's
+        entry.module.path = path.to_string_lossy().to_string();
+        let module = entry.module.clone();
+        self.fresh.lock().unwrap().insert(absolute, entry);
+        Ok(module)
+    }
+
+    /// Stores the modules of this run along with the ones of earlier runs, e.g. when
+    /// only a subdirectory was collected. Modules whose files are gone are dropped
+    pub fn save(self) -> Result<()> {
+        let Some(file) = self.file else {
+            return Ok(());
+        };
+        let mut entries = self.entries;
+        entries.extend(self.fresh.into_inner().unwrap());
+        let mut modules: Vec<(PathBuf, Entry)> = entries
+            .into_iter()
+            .filter(|(path, _)| path.is_file())
+            .collect();
+        modules.sort_by(|a, b| a.0.cmp(&b.0));
+        let cache = CacheFile {
+            key: self.key,
+            modules,
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+            // keeps the cache out of git, like pytest does for its own cache
+            fs::write(dir.join(".gitignore"), "*\n")?;
+        }
+        // written next to the cache and renamed, so that concurrent runs
+        // never read a partially written file
+        let tmp = file.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_vec(&cache)?)?;
+        fs::rename(tmp, file)?;
+        Ok(())
+    }
+}
+
+fn read_entries(file: &Path, key: &str) -> Option<HashMap<PathBuf, Entry>> {
+    let contents = fs::read(file).ok()?;
+    let cache: CacheFile = serde_json::from_slice(&contents).ok()?;
+    (cache.key == key).then(|| cache.modules.into_iter().collect())
+}
+
+/// Parsed modules depend on the pytexp version and the target, the rest of
+/// the collection config is part of the key as well to keep it simple
+fn cache_key(config: &Config) -> String {
+    let config = Config {
+        jobs: None,
+        ..config.clone()
+    };
+    format!("{} {config:?}", env!("CARGO_PKG_VERSION"))
+}
+
+pub fn content_hash(contents: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(contents.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempProject;
+
+    #[test]
+    fn test_modules_are_reused() {
+        let project = TempProject::new(&[("test_cached.py", "def test_one():\n    pass\n")]);
+        let path = project.path("test_cached.py");
+        let config = Config {
+            rootdir: project.root.clone(),
+            ..Default::default()
+        };
+
+        let cache = ModuleCache::load(&config, false);
+        assert_eq!(cache.parse_module(&path, &config).unwrap().items.len(), 1);
+        cache.save().unwrap();
+
+        // a cached module is returned without parsing the file again
+        let mut cache = ModuleCache::load(&config, false);
+        let entry = cache.entries.get_mut(&path).unwrap();
+        entry.module.items.clear();
+        entry.module.path = "collected/from/elsewhere.py".to_string();
+        let module = cache.parse_module(&path, &config).unwrap();
+        assert!(module.items.is_empty());
+        // paths of cached modules are the ones of this invocation
+        assert_eq!(module.path, path.to_string_lossy());
+
+        // changed contents are parsed again
+        project.write(
+            "test_cached.py",
+            "def test_one():\n    pass\n\n\ndef test_two():\n    pass\n",
+        );
+        let cache = ModuleCache::load(&config, false);
+        assert_eq!(cache.parse_module(&path, &config).unwrap().items.len(), 2);
+
+        // another config invalidates the cache
+        let other = Config {
+            python_functions: vec!["check".to_string()],
+            ..config.clone()
+        };
+        assert!(ModuleCache::load(&other, false).entries.is_empty());
+        assert!(ModuleCache::load(&config, true).entries.is_empty());
+    }
+
+    #[test]
+    fn test_narrowed_run_keeps_other_modules() {
+        let project = TempProject::new(&[
+            ("unit/test_a.py", "def test_a():\n    pass\n"),
+            ("api/test_b.py", "def test_b():\n    pass\n"),
+        ]);
+        let (a, b) = (
+            project.path("unit/test_a.py"),
+            project.path("api/test_b.py"),
+        );
+        let config = Config {
+            rootdir: project.root.clone(),
+            ..Default::default()
+        };
+        let cache = ModuleCache::load(&config, false);
+        cache.parse_module(&a, &config).unwrap();
+        cache.parse_module(&b, &config).unwrap();
+        cache.save().unwrap();
+
+        // only `unit` is collected
+        let cache = ModuleCache::load(&config, false);
+        cache.parse_module(&a, &config).unwrap();
+        cache.save().unwrap();
+        let cache = ModuleCache::load(&config, false);
+        assert!(cache.entries.contains_key(&a));
+        assert!(cache.entries.contains_key(&b));
+
+        // modules of deleted files are dropped
+        fs::remove_file(&b).unwrap();
+        cache.save().unwrap();
+        let cache = ModuleCache::load(&config, false);
+        assert!(cache.entries.contains_key(&a));
+        assert!(!cache.entries.contains_key(&b));
+    }
+}
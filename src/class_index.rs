@@ -0,0 +1,610 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::entities::{
+    Collection, ExcludedItem, ExpectedOutcome, ItemKind, ModuleItem, Parametrization, ParsedClass,
+    ParsedFunction, ParsedModule, ParsedTest,
+};
+use crate::markers;
+use crate::parametrize::{self, ParamIds};
+use crate::parser;
+use crate::target::Target;
+
+/// protects import and inheritance resolution against cycles
+const MAX_DEPTH: usize = 32;
+
+/// class is identified by the module file and its name in the module
+type ClassKey = (PathBuf, String);
+
+/// Base classes which make a class a unittest test case, imports are resolved
+/// to these names before the check. Third party ones are subclasses of
+/// `unittest.TestCase` which can't be followed in the sources
+const UNITTEST_BASES: &[&str] = &[
+    "unittest.TestCase",
+    "unittest.case.TestCase",
+    "unittest.IsolatedAsyncioTestCase",
+    "unittest.async_case.IsolatedAsyncioTestCase",
+    "asynctest.TestCase",
+    "django.test.SimpleTestCase",
+    "django.test.TestCase",
+    "django.test.TransactionTestCase",
+    "django.test.LiveServerTestCase",
+    "twisted.trial.unittest.TestCase",
+    "twisted.trial.unittest.SynchronousTestCase",
+];
+
+/// How pytest collects a class
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ClassKind {
+    /// matches `python_classes` or has `__test__ = True`, methods match `python_functions`
+    Python,
+    /// subclass of `unittest.TestCase` whatever its name is, methods start with `test`
+    UnitTest,
+    NotTest,
+    /// looks like a test class, but pytest doesn't collect it
+    Excluded(&'static str),
+}
+
+/// Index of parsed modules used to resolve base classes across files.
+/// Modules which are imported by test modules are parsed on demand.
+#[derive(Default)]
+pub struct ClassIndex {
+    modules: HashMap<PathBuf, Option<ParsedModule>>,
+    mro_cache: HashMap<ClassKey, Vec<ClassKey>>,
+    /// modules parsed on demand are parsed for the same target
+    target: Target,
+}
+
+impl ClassIndex {
+    pub fn new(target: Target) -> Self {
+        ClassIndex {
+            target,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, module: ParsedModule) {
+        self.mro_cache.clear();
+        self.modules
+            .insert(PathBuf::from(&module.path), Some(module));
+    }
+
+    fn module(&self, path: &Path) -> Option<&ParsedModule> {
+        self.modules.get(path).and_then(Option::as_ref)
+    }
+
+    fn class(&self, key: &ClassKey) -> Option<&ParsedClass> {
+        self.module(&key.0).and_then(|m| m.find_class(&key.1))
+    }
+
+    fn load(&mut self, path: &Path) -> bool {
+        if !self.modules.contains_key(path) {
+            let module = match path.is_file() {
+                true => parser::parse_module(path, &self.target).ok(),
+                false => None,
+            };
+            self.modules.insert(path.to_path_buf(), module);
+        }
+        self.module(path).is_some()
+    }
+
+    /// Tests of the module in definition order, inherited methods included,
+    /// and the test-like items which are left out
+    pub fn collect_tests(&mut self, path: &Path, config: &Config) -> Collection {
+        let mut collection = Collection::default();
+        let Some(module) = self.module(path).cloned() else {
+            return collection;
+        };
+        let module_marks = ParentMarks {
+            markers: module.markers.clone(),
+            expected: module.expected.clone(),
+            parametrize: vec![],
+        };
+        for item in &module.items {
+            match item {
+                ModuleItem::Function(function)
+                    if config.is_test_function(&function.name)
+                        && function.dunder_test == Some(false) =>
+                {
+                    collection.excluded.push(excluded(
+                        &module.path,
+                        &function.name,
+                        function.row_location,
+                        "`__test__ = False`",
+                    ));
+                }
+                ModuleItem::Function(function)
+                    if config.is_test_function(&function.name)
+                        || function.dunder_test == Some(true) =>
+                {
+                    add_function(
+                        &function.name,
+                        function,
+                        &module_marks,
+                        &module.path,
+                        &module.path,
+                        &mut collection.tests,
+                    );
+                }
+                ModuleItem::Assignment { name, row_location } if config.is_test_function(name) => {
+                    collection.excluded.push(excluded(
+                        &module.path,
+                        name,
+                        *row_location,
+                        "not a function",
+                    ));
+                }
+                ModuleItem::Class(class) => {
+                    self.collect_class(path, class, &[], &module_marks, config, &mut collection);
+                }
+                _ => {}
+            }
+        }
+        collection
+    }
+
+    /// Adds the class row and the tests of the class, nested classes included.
+    /// `outer` holds names of the classes the class is defined in
+    fn collect_class(
+        &mut self,
+        path: &Path,
+        class: &ParsedClass,
+        outer: &[&str],
+        outer_marks: &ParentMarks,
+        config: &Config,
+        collection: &mut Collection,
+    ) {
+        let mut names = outer.to_vec();
+        names.push(&class.name);
+        let filepath = path.to_string_lossy();
+        let node_name = names.join("::");
+        let key = (path.to_path_buf(), names.join("."));
+        let kind = match self.class_kind(&key, config) {
+            ClassKind::NotTest => return,
+            ClassKind::Excluded(reason) => {
+                let item = excluded(&filepath, &node_name, class.row_location, reason);
+                collection.excluded.push(item);
+                return;
+            }
+            kind => kind,
+        };
+        let (class_marks, mut tests, own) =
+            self.class_tests(&key, &node_name, kind, outer_marks, config, collection);
+        let mut nested = vec![];
+        let mut nested_excluded = vec![];
+        for inner in &class.classes {
+            let mut inner_collection = Collection::default();
+            self.collect_class(
+                path,
+                inner,
+                &names,
+                &class_marks,
+                config,
+                &mut inner_collection,
+            );
+            nested.push((inner.row_location, inner_collection.tests));
+            nested_excluded.extend(inner_collection.excluded);
+        }
+        // like pytest, methods and nested classes are listed in definition order
+        let mut nested = nested.into_iter().peekable();
+        for test in own {
+            while let Some((_, inner)) = nested.next_if(|(row, _)| *row < test.row_location) {
+                tests.extend(inner);
+            }
+            tests.push(test);
+        }
+        tests.extend(nested.flat_map(|(_, inner)| inner));
+        if !tests.is_empty() {
+            let mut class_test = ParsedTest::new(node_name, class.row_location, &filepath);
+            class_test.end_row_location = class.end_row_location;
+            class_test.kind = match kind {
+                ClassKind::UnitTest => ItemKind::UnitTestCase,
+                _ => ItemKind::Class,
+            };
+            class_test.expected = class_marks.strongest_expected();
+            class_test.markers = class_marks.markers;
+            collection.tests.push(class_test);
+            collection.tests.extend(tests);
+        }
+        collection.excluded.extend(nested_excluded);
+    }
+
+    /// Marks of the class, inherited and outer ones included, the tests inherited from
+    /// base classes and the tests defined in the class itself
+    fn class_tests(
+        &mut self,
+        key: &ClassKey,
+        node_name: &str,
+        kind: ClassKind,
+        outer_marks: &ParentMarks,
+        config: &Config,
+        collection: &mut Collection,
+    ) -> (ParentMarks, Vec<ParsedTest>, Vec<ParsedTest>) {
+        let mro = self.mro(key, 0);
+        let filepath = key.0.to_string_lossy();
+
+        let mut class_marks = ParentMarks::default();
+        for c in mro.iter().filter_map(|k| self.class(k)) {
+            markers::merge(&mut class_marks.markers, &c.markers);
+            class_marks.expected.extend(c.expected.iter().cloned());
+        }
+        // marks of base classes are applied before the ones of subclasses
+        class_marks.parametrize = mro
+            .iter()
+            .rev()
+            .filter_map(|k| self.class(k))
+            .flat_map(|c| c.parametrize.iter().cloned())
+            .collect();
+        markers::merge(&mut class_marks.markers, &outer_marks.markers);
+        class_marks
+            .expected
+            .extend(outer_marks.expected.iter().cloned());
+        class_marks
+            .parametrize
+            .extend(outer_marks.parametrize.iter().cloned());
+
+        // like pytest, walk the MRO so that overridden methods are collected once,
+        // but list tests of base classes before the ones of subclasses
+        let is_test = |name: &str| match kind {
+            ClassKind::UnitTest => name.starts_with("test"),
+            _ => config.is_test_function(name),
+        };
+        let mut seen = HashSet::new();
+        let mut tests_per_class = vec![];
+        for k in &mro {
+            let Some(c) = self.class(k) else {
+                continue;
+            };
+            let source_path = k.0.to_string_lossy();
+            let mut tests = vec![];
+            for method in &c.methods {
+                if seen.insert(method.name.clone()) && is_test(&method.name) {
+                    add_function(
+                        &format!("{node_name}::{}", method.name),
+                        method,
+                        &class_marks,
+                        &filepath,
+                        &source_path,
+                        &mut tests,
+                    );
+                }
+            }
+            if k == key {
+                for attribute in &c.attributes {
+                    let name = &attribute.name;
+                    let is_method = c.methods.iter().any(|m| &m.name == name);
+                    if !is_method && seen.insert(name.clone()) && is_test(name) {
+                        collection.excluded.push(excluded(
+                            &filepath,
+                            &format!("{node_name}::{name}"),
+                            attribute.row_location,
+                            "not a function",
+                        ));
+                    }
+                }
+            }
+            seen.extend(c.attributes.iter().map(|a| a.name.clone()));
+            tests_per_class.push(tests);
+        }
+        if kind == ClassKind::UnitTest {
+            for test in tests_per_class.iter_mut().flatten() {
+                test.kind = ItemKind::TestCaseFunction;
+            }
+        }
+        // the class itself comes first in its MRO
+        let own = tests_per_class.remove(0);
+        let inherited = tests_per_class.into_iter().rev().flatten().collect();
+        (class_marks, inherited, own)
+    }
+
+    /// Whether and how pytest collects the class: `__test__ = False` turns collection off,
+    /// classes with `__init__` or `__new__` are skipped with a warning unless they are
+    /// unittest test cases
+    fn class_kind(&mut self, key: &ClassKey, config: &Config) -> ClassKind {
+        let mro = self.mro(key, 0);
+        let Some(class) = self.class(key) else {
+            return ClassKind::NotTest;
+        };
+        let classes: Vec<(&Path, &ParsedClass)> = mro
+            .iter()
+            .filter_map(|k| self.class(k).map(|c| (k.0.as_path(), c)))
+            .collect();
+        let dunder_test = classes.iter().find_map(|(_, c)| c.dunder_test);
+        let is_unittest = classes.iter().any(|(module, c)| {
+            c.bases.iter().any(|base| {
+                let name = self.qualified_name(module, base);
+                UNITTEST_BASES.contains(&name.as_str())
+            })
+        });
+        if !is_unittest && !config.is_test_class(&class.name) && dunder_test != Some(true) {
+            return ClassKind::NotTest;
+        }
+        if dunder_test == Some(false) {
+            return ClassKind::Excluded("`__test__ = False`");
+        }
+        if is_unittest {
+            return ClassKind::UnitTest;
+        }
+        let has_method = |name: &str| {
+            classes
+                .iter()
+                .any(|(_, c)| c.methods.iter().any(|m| m.name == name))
+        };
+        if has_method("__init__") {
+            return ClassKind::Excluded("has a `__init__` constructor");
+        }
+        if has_method("__new__") {
+            return ClassKind::Excluded("has a `__new__` constructor");
+        }
+        ClassKind::Python
+    }
+
+    /// Dotted name with the imported head replaced by what it is imported from,
+    /// e.g. `unittest.TestCase` for `TestCase` after `from unittest import TestCase`
+    fn qualified_name(&self, module: &Path, name: &str) -> String {
+        let (head, rest) = match name.split_once('.') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (name, None),
+        };
+        let imported = self.module(module).and_then(|m| m.imports.get(head));
+        match (imported, rest) {
+            (Some(target), Some(rest)) => format!("{target}.{rest}"),
+            (Some(target), None) => target.clone(),
+            (None, _) => name.to_string(),
+        }
+    }
+
+    /// C3 linearization over base classes that could be resolved
+    fn mro(&mut self, key: &ClassKey, depth: usize) -> Vec<ClassKey> {
+        if let Some(mro) = self.mro_cache.get(key) {
+            return mro.clone();
+        }
+        let bases_names = match self.class(key) {
+            Some(class) if depth < MAX_DEPTH => class.bases.clone(),
+            _ => return vec![key.clone()],
+        };
+        let bases: Vec<ClassKey> = bases_names
+            .iter()
+            .filter_map(|name| self.resolve_class(&key.0, name, depth + 1))
+            .filter(|base| base != key)
+            .collect();
+        let mut sequences: Vec<Vec<ClassKey>> =
+            bases.iter().map(|base| self.mro(base, depth + 1)).collect();
+        sequences.push(bases);
+
+        let mut mro = vec![key.clone()];
+        match c3_merge(sequences.clone()) {
+            Some(merged) => mro.extend(merged),
+            // python refuses such hierarchies, fall back to depth first order
+            None => {
+                for k in sequences.into_iter().flatten() {
+                    if !mro.contains(&k) {
+                        mro.push(k);
+                    }
+                }
+            }
+        }
+        self.mro_cache.insert(key.clone(), mro.clone());
+        mro
+    }
+
+    /// Finds the class a (possibly dotted) name refers to inside the module
+    fn resolve_class(&mut self, module: &Path, name: &str, depth: usize) -> Option<ClassKey> {
+        if depth > MAX_DEPTH || !self.load(module) {
+            return None;
+        }
+        let m = self.module(module)?;
+        if m.find_class(name).is_some() {
+            return Some((module.to_path_buf(), name.to_string()));
+        }
+        let (head, rest) = match name.split_once('.') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (name, None),
+        };
+        let targets: Vec<String> = match m.imports.get(head) {
+            Some(target) => vec![match rest {
+                Some(rest) => format!("{target}.{rest}"),
+                None => target.clone(),
+            }],
+            None => m
+                .star_imports
+                .iter()
+                .map(|star| format!("{star}.{name}"))
+                .collect(),
+        };
+        targets
+            .iter()
+            .find_map(|target| self.resolve_import(module, target, depth + 1))
+    }
+
+    /// Resolves `package.module.Class` (or `..module.Class`) imported from the module
+    fn resolve_import(&mut self, from: &Path, target: &str, depth: usize) -> Option<ClassKey> {
+        let relative_level = target.chars().take_while(|c| *c == '.').count();
+        let segments: Vec<&str> = target[relative_level..].split('.').collect();
+
+        let search_dirs = match relative_level {
+            0 => import_roots(from),
+            level => {
+                let mut dir = from.parent()?.to_path_buf();
+                for _ in 1..level {
+                    dir = dir.parent()?.to_path_buf();
+                }
+                vec![dir]
+            }
+        };
+
+        // the longest existing module wins, the rest is a class inside of it.
+        // `from . import Class` refers to the package itself
+        let shortest = if relative_level > 0 { 0 } else { 1 };
+        for split in (shortest..segments.len()).rev() {
+            let (module_segments, attr) = segments.split_at(split);
+            if attr.len() != 1 {
+                continue;
+            }
+            for dir in &search_dirs {
+                for candidate in module_files(dir, module_segments) {
+                    if self.load(&candidate) {
+                        if let Some(key) = self.resolve_class(&candidate, attr[0], depth + 1) {
+                            return Some(key);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Directories absolute imports are looked up in: the basedir pytest inserts
+/// into sys.path for the module (first directory upwards without `__init__.py`)
+/// and the current working directory
+fn import_roots(module: &Path) -> Vec<PathBuf> {
+    let mut basedir = module.parent().unwrap_or(Path::new("")).to_path_buf();
+    while basedir.join("__init__.py").is_file() {
+        match basedir.parent() {
+            Some(parent) => basedir = parent.to_path_buf(),
+            None => break,
+        }
+    }
+    let mut roots = vec![basedir];
+    if roots[0] != Path::new("") {
+        roots.push(PathBuf::new());
+    }
+    roots
+}
+
+fn module_files(dir: &Path, segments: &[&str]) -> Vec<PathBuf> {
+    let mut path = dir.to_path_buf();
+    if segments.is_empty() {
+        return vec![path.join("__init__.py")];
+    }
+    path.extend(segments);
+    vec![path.with_extension("py"), path.join("__init__.py")]
+}
+
+fn c3_merge(mut sequences: Vec<Vec<ClassKey>>) -> Option<Vec<ClassKey>> {
+    let mut result = vec![];
+    loop {
+        sequences.retain(|s| !s.is_empty());
+        if sequences.is_empty() {
+            return Some(result);
+        }
+        let head = sequences
+            .iter()
+            .map(|s| &s[0])
+            .find(|candidate| !sequences.iter().any(|s| s[1..].contains(candidate)))?
+            .clone();
+        for s in sequences.iter_mut() {
+            if s[0] == head {
+                s.remove(0);
+            }
+        }
+        result.push(head);
+    }
+}
+
+/// Marks a test gets from its classes and module
+#[derive(Default)]
+struct ParentMarks {
+    markers: Vec<String>,
+    expected: Vec<ExpectedOutcome>,
+    /// parametrize marks of the classes
+    parametrize: Vec<ParamIds>,
+}
+
+impl ParentMarks {
+    fn strongest_expected(&self) -> Option<ExpectedOutcome> {
+        ExpectedOutcome::strongest(&self.expected).cloned()
+    }
+}
+
+fn excluded(filepath: &str, node_name: &str, row_location: usize, reason: &str) -> ExcludedItem {
+    ExcludedItem {
+        node_id: format!("{filepath}::{node_name}"),
+        row_location,
+        reason: reason.to_string(),
+    }
+}
+
+/// Adds the test function, expanded into one test per parametrize id
+fn add_function(
+    name: &str,
+    function: &ParsedFunction,
+    parent_marks: &ParentMarks,
+    filepath: &str,
+    source_path: &str,
+    input: &mut Vec<ParsedTest>,
+) {
+    let marks: Vec<ParamIds> = function
+        .parametrize
+        .iter()
+        .chain(&parent_marks.parametrize)
+        .cloned()
+        .collect();
+    let mut test_markers = function.markers.clone();
+    markers::merge(&mut test_markers, &parent_marks.markers);
+    let expected =
+        ExpectedOutcome::strongest(function.expected.iter().chain(&parent_marks.expected));
+    let mut push = |name: String, parametrization: Option<Parametrization>| {
+        let mut test = ParsedTest::new(name, function.row_location, filepath);
+        test.end_row_location = function.end_row_location;
+        test.parametrization = parametrization;
+        test.source_path = source_path.to_string();
+        test.markers = test_markers.clone();
+        test.expected = expected.cloned();
+        test.source_hash = function.source_hash;
+        test.fixtures = function.fixtures.clone();
+        input.push(test);
+    };
+    if marks.is_empty() {
+        push(name.to_string(), None);
+        return;
+    }
+    match parametrize::expand(&marks) {
+        Some(ids) => {
+            for id in ids {
+                push(format!("{name}[{id}]"), Some(Parametrization::Id(id)));
+            }
+        }
+        None => push(name.to_string(), Some(Parametrization::Unresolved)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tests_inherited_from_other_modules() {
+        let mut index = ClassIndex::default();
+        for (path, source) in [
+            (
+                "tests/mixins.py",
+                "class BaseTests:\n    def test_from_mixin(self):\n        pass\n",
+            ),
+            (
+                "tests/test_child.py",
+                "from mixins import BaseTests\n\n\nclass TestChild(BaseTests):\n    def test_own(self):\n        pass\n",
+            ),
+        ] {
+            let module = parser::parse_module_source(source, path, &Target::default()).unwrap();
+            index.insert(module);
+        }
+        let collection = index.collect_tests(Path::new("tests/test_child.py"), &Config::default());
+
+        let tests: Vec<(&str, usize, &str)> = collection
+            .tests
+            .iter()
+            .map(|t| (t.test_name.as_str(), t.row_location, t.source_path.as_str()))
+            .collect();
+        assert_eq!(
+            tests,
+            vec![
+                ("TestChild", 4, "tests/test_child.py"),
+                ("TestChild::test_from_mixin", 2, "tests/mixins.py"),
+                ("TestChild::test_own", 5, "tests/test_child.py"),
+            ]
+        );
+    }
+}
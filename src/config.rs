@@ -0,0 +1,638 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::{Context, Result};
+use walkdir::WalkDir;
+
+use crate::target::Target;
+
+/// Config files in the order pytest looks for them in every directory
+const CONFIG_FILES: [&str; 5] = [
+    "pytest.ini",
+    ".pytest.ini",
+    "pyproject.toml",
+    "tox.ini",
+    "setup.cfg",
+];
+
+/// Subset of pytest ini options which affects test discovery
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub rootdir: PathBuf,
+    pub inifile: Option<PathBuf>,
+    pub testpaths: Vec<String>,
+    pub python_files: Vec<String>,
+    pub python_classes: Vec<String>,
+    pub python_functions: Vec<String>,
+    pub norecursedirs: Vec<String>,
+    /// python version and platform `if` conditions are evaluated against
+    pub target: Target,
+    /// threads modules are parsed on, all available cores when None
+    pub jobs: Option<NonZeroUsize>,
+    /// `--ignore` paths of `addopts`, relative to the invocation dir
+    pub ignore: Vec<String>,
+    /// `--ignore-glob` patterns of `addopts`
+    pub ignore_glob: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rootdir: PathBuf::new(),
+            inifile: None,
+            testpaths: vec![],
+            python_files: args(&["test_*.py", "*_test.py"]),
+            python_classes: args(&["Test"]),
+            python_functions: args(&["test"]),
+            norecursedirs: args(&[
+                "*.egg",
+                ".*",
+                "_darcs",
+                "build",
+                "CVS",
+                "dist",
+                "node_modules",
+                "venv",
+                "{arch}",
+            ]),
+            target: Target::default(),
+            jobs: None,
+            ignore: vec![],
+            ignore_glob: vec![],
+        }
+    }
+}
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+impl Config {
+    /// Finds rootdir and config file for the invocation dir the way pytest does
+    pub fn load(invocation_dir: &Path) -> Result<Config> {
+        for dir in invocation_dir.ancestors() {
+            for name in CONFIG_FILES {
+                let path = dir.join(name);
+                if !path.is_file() {
+                    continue;
+                }
+                if let Some(options) = read_options(&path)? {
+                    return Ok(Config::from_options(dir, Some(path), &options));
+                }
+            }
+        }
+        let rootdir = invocation_dir
+            .ancestors()
+            .find(|dir| dir.join("setup.py").is_file())
+            .unwrap_or(invocation_dir);
+        Ok(Config::from_options(rootdir, None, &HashMap::new()))
+    }
+
+    /// Like `load`, for the paths of the positional arguments and the `--rootdir`
+    /// and `-c` options. Like pytest, the config file is looked for from the common
+    /// ancestor of the paths, and a given config file's directory is the rootdir
+    pub fn load_for_args(
+        invocation_dir: &Path,
+        paths: &[PathBuf],
+        rootdir: Option<&Path>,
+        inifile: Option<&Path>,
+    ) -> Result<Config> {
+        let mut config = match inifile {
+            Some(path) => {
+                let path = invocation_dir
+                    .join(path)
+                    .canonicalize()
+                    .with_context(|| format!("config file not found: {}", path.display()))?;
+                // pytest reads the given file even without a pytest section
+                let options = read_options(&path)?.unwrap_or_default();
+                let dir = path.parent().unwrap_or(invocation_dir).to_path_buf();
+                Config::from_options(&dir, Some(path), &options)
+            }
+            None => Config::load(&common_ancestor(invocation_dir, paths))?,
+        };
+        if let Some(rootdir) = rootdir {
+            config.rootdir = invocation_dir
+                .join(rootdir)
+                .canonicalize()
+                .with_context(|| format!("rootdir not found: {}", rootdir.display()))?;
+        }
+        Ok(config)
+    }
+
+    fn from_options(rootdir: &Path, inifile: Option<PathBuf>, options: &Options) -> Config {
+        let mut config = Config {
+            rootdir: rootdir.to_path_buf(),
+            inifile,
+            ..Default::default()
+        };
+        let fields = [
+            ("testpaths", &mut config.testpaths),
+            ("python_files", &mut config.python_files),
+            ("python_classes", &mut config.python_classes),
+            ("python_functions", &mut config.python_functions),
+            ("norecursedirs", &mut config.norecursedirs),
+        ];
+        for (name, field) in fields {
+            if let Some(value) = options.get(name) {
+                *field = value.clone();
+            }
+        }
+        let addopts = options
+            .get("addopts")
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        config.ignore = option_values(addopts, "--ignore");
+        config.ignore_glob = option_values(addopts, "--ignore-glob");
+        config
+    }
+
+    /// Paths to collect relative to the invocation dir.
+    /// Like pytest, testpaths are used only when running from the rootdir.
+    pub fn collect_paths(&self, invocation_dir: &Path) -> Vec<PathBuf> {
+        if self.testpaths.is_empty() || invocation_dir != self.rootdir {
+            return vec![PathBuf::from(".")];
+        }
+        let mut paths = vec![];
+        for testpath in &self.testpaths {
+            match has_magic(testpath) {
+                true => paths.extend(expand_glob(invocation_dir, testpath)),
+                false => paths.push(PathBuf::from(testpath)),
+            }
+        }
+        paths
+    }
+
+    /// Number of threads modules are parsed on
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    pub fn is_test_file(&self, path: &Path) -> bool {
+        path.extension().map_or(false, |ext| ext == "py")
+            && self.python_files.iter().any(|p| fnmatch_path(p, path))
+    }
+
+    pub fn is_test_class(&self, name: &str) -> bool {
+        matches_prefix_or_glob(&self.python_classes, name)
+    }
+
+    pub fn is_test_function(&self, name: &str) -> bool {
+        matches_prefix_or_glob(&self.python_functions, name)
+    }
+
+    pub fn is_norecursedir(&self, path: &Path) -> bool {
+        self.norecursedirs.iter().any(|p| fnmatch_path(p, path))
+    }
+}
+
+type Options = HashMap<String, Vec<String>>;
+
+/// Deepest directory the paths are in, the invocation dir without paths
+fn common_ancestor(invocation_dir: &Path, paths: &[PathBuf]) -> PathBuf {
+    let mut ancestor: Option<PathBuf> = None;
+    for path in paths {
+        let path = invocation_dir.join(path);
+        let path = path.canonicalize().unwrap_or(path);
+        let dir = match path.is_file() {
+            true => path.parent().unwrap_or(&path).to_path_buf(),
+            false => path,
+        };
+        ancestor = Some(match ancestor {
+            Some(ancestor) => ancestor
+                .ancestors()
+                .find(|a| dir.starts_with(a))
+                .unwrap_or(&ancestor)
+                .to_path_buf(),
+            None => dir,
+        });
+    }
+    ancestor.unwrap_or_else(|| invocation_dir.to_path_buf())
+}
+
+/// None when the file has no pytest section
+fn read_options(path: &Path) -> Result<Option<Options>> {
+    let contents = std::fs::read_to_string(path)?;
+    let name = path.file_name().unwrap().to_string_lossy();
+    match name.as_ref() {
+        "pyproject.toml" => {
+            let document: toml::Value = toml::from_str(&contents)
+                .with_context(|| format!("can't parse {}", path.display()))?;
+            let options = document
+                .get("tool")
+                .and_then(|t| t.get("pytest"))
+                .and_then(|t| t.get("ini_options"))
+                .and_then(|t| t.as_table());
+            Ok(options.map(|table| {
+                table
+                    .iter()
+                    .map(|(key, value)| (key.clone(), toml_args(value)))
+                    .collect()
+            }))
+        }
+        _ => {
+            let section = match name.as_ref() {
+                "setup.cfg" => "tool:pytest",
+                _ => "pytest",
+            };
+            let sections = parse_ini(&contents);
+            match sections.get(section) {
+                Some(options) => Ok(Some(
+                    options
+                        .iter()
+                        .map(|(key, value)| (key.clone(), split_args(value)))
+                        .collect(),
+                )),
+                // pytest.ini is the config file even without the [pytest] section
+                None if name == "pytest.ini" => Ok(Some(HashMap::new())),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// Values of a command line option given as `--name value` or `--name=value`
+fn option_values(args: &[String], name: &str) -> Vec<String> {
+    let mut values = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == name {
+            values.extend(args.next().cloned());
+        } else if let Some(value) = arg.strip_prefix(name).and_then(|a| a.strip_prefix('=')) {
+            values.push(value.to_string());
+        }
+    }
+    values
+}
+
+fn toml_args(value: &toml::Value) -> Vec<String> {
+    match value {
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|v| match v {
+                toml::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect(),
+        toml::Value::String(s) => split_args(s),
+        other => vec![other.to_string()],
+    }
+}
+
+/// sections -> keys -> raw values, continuation lines are joined with newlines
+fn parse_ini(contents: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut section = String::new();
+    let mut key: Option<String> = None;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        if trimmed.is_empty() {
+            key = None;
+            continue;
+        }
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            sections.entry(section.clone()).or_default();
+            key = None;
+            continue;
+        }
+        let options = sections.entry(section.clone()).or_default();
+        if line.starts_with(char::is_whitespace) {
+            if let Some(value) = key.as_ref().and_then(|k| options.get_mut(k)) {
+                value.push('\n');
+                value.push_str(trimmed);
+            }
+            continue;
+        }
+        if let Some(position) = trimmed.find(['=', ':']) {
+            let (k, v) = trimmed.split_at(position);
+            let k = k.trim().to_string();
+            options.insert(k.clone(), v[1..].trim().to_string());
+            key = Some(k);
+        }
+    }
+    sections
+}
+
+/// Splits a value like shlex.split does for pytest "args" ini options
+pub fn split_args(value: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    result.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        result.push(current);
+    }
+    result
+}
+
+fn matches_prefix_or_glob(options: &[String], name: &str) -> bool {
+    options.iter().any(|option| {
+        name.starts_with(option.as_str()) || (has_magic(option) && fnmatch(option, name))
+    })
+}
+
+fn has_magic(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// pytest's fnmatch_ex: patterns without a separator are matched against the file name
+pub fn fnmatch_path(pattern: &str, path: &Path) -> bool {
+    if !pattern.contains('/') {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        return fnmatch(pattern, &name);
+    }
+    let path = path.to_string_lossy();
+    if path.starts_with('/') && !pattern.starts_with('/') {
+        return fnmatch(&format!("*/{pattern}"), &path);
+    }
+    fnmatch(pattern, &path)
+}
+
+/// Python's fnmatch.fnmatchcase: `*`, `?`, `[seq]` and `[!seq]`
+pub fn fnmatch(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    fnmatch_chars(&pattern, &name)
+}
+
+fn fnmatch_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| fnmatch_chars(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && fnmatch_chars(&pattern[1..], &name[1..]),
+        Some('[') => match parse_class(&pattern[1..]) {
+            Some((negated, ranges, rest)) => match name.first() {
+                Some(c) => {
+                    let found = ranges.iter().any(|(from, to)| from <= c && c <= to);
+                    found != negated && fnmatch_chars(rest, &name[1..])
+                }
+                None => false,
+            },
+            // unclosed `[` is a literal
+            None => name.first() == Some(&'[') && fnmatch_chars(&pattern[1..], &name[1..]),
+        },
+        Some(c) => name.first() == Some(c) && fnmatch_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+type CharRanges = Vec<(char, char)>;
+
+/// parses the body of `[...]`, returns the pattern after the closing bracket
+fn parse_class(pattern: &[char]) -> Option<(bool, CharRanges, &[char])> {
+    let (negated, mut i) = match pattern.first() {
+        Some('!') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut ranges = vec![];
+    let start = i;
+    while i < pattern.len() {
+        let c = pattern[i];
+        if c == ']' && i > start {
+            return Some((negated, ranges, &pattern[i + 1..]));
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            ranges.push((c, pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Expands a glob like `tests/*/unit` relative to the base dir into existing paths,
+/// relative to the base dir too. Like python's `glob.glob(recursive=True)`, `**`
+/// matches any number of directories
+fn expand_glob(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+    for segment in pattern.split('/').filter(|s| !s.is_empty()) {
+        let mut next = vec![];
+        for path in paths {
+            if segment == "**" {
+                let mut matched: Vec<PathBuf> = WalkDir::new(base.join(&path))
+                    .follow_links(false)
+                    .into_iter()
+                    .filter_entry(|e| {
+                        e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.')
+                    })
+                    .filter_map(|e| e.ok())
+                    .map(|e| match e.depth() {
+                        0 => path.clone(),
+                        _ => path.join(e.path().strip_prefix(base.join(&path)).unwrap()),
+                    })
+                    .collect();
+                matched.sort();
+                next.extend(matched);
+                continue;
+            }
+            if !has_magic(segment) {
+                let candidate = path.join(segment);
+                if base.join(&candidate).exists() {
+                    next.push(candidate);
+                }
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(base.join(&path)) else {
+                continue;
+            };
+            let mut matched: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok())
+                .filter(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    // like python's glob, hidden names match only patterns starting with `.`
+                    (!name.starts_with('.') || segment.starts_with('.')) && fnmatch(segment, &name)
+                })
+                .map(|e| path.join(e.file_name()))
+                .collect();
+            matched.sort();
+            next.extend(matched);
+        }
+        paths = next;
+    }
+    // a leading `**` matches the base dir itself, which isn't a path to collect
+    paths.retain(|path| !path.as_os_str().is_empty());
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempProject;
+
+    #[test]
+    fn test_fnmatch() {
+        assert!(fnmatch("test_*.py", "test_client.py"));
+        assert!(fnmatch("*_test.py", "client_test.py"));
+        assert!(!fnmatch("test_*.py", "conftest.py"));
+        assert!(fnmatch(".*", ".tox"));
+        assert!(fnmatch("check_[a-c]?.py", "check_b1.py"));
+        assert!(!fnmatch("check_[!a-c]?.py", "check_b1.py"));
+        assert!(fnmatch_path(
+            "tests/*/legacy",
+            Path::new("/repo/tests/unit/legacy")
+        ));
+    }
+
+    #[test]
+    fn test_ini_options() {
+        let contents = "[metadata]\nname = pkg\n\n[tool:pytest]\n# comment\ntestpaths = src tests\npython_files =\n    check_*.py\n    test_*.py\npython_functions = \"check it\"\n";
+        let sections = parse_ini(contents);
+        let options = &sections["tool:pytest"];
+
+        assert_eq!(split_args(&options["testpaths"]), vec!["src", "tests"]);
+        assert_eq!(
+            split_args(&options["python_files"]),
+            vec!["check_*.py", "test_*.py"]
+        );
+        assert_eq!(split_args(&options["python_functions"]), vec!["check it"]);
+
+        let addopts = split_args("-ra --ignore=vendor --ignore legacy --ignore-glob '*_py2.py'");
+        let config = Config::from_options(
+            Path::new(""),
+            None,
+            &HashMap::from([
+                ("python_classes".to_string(), args(&["*Suite"])),
+                ("addopts".to_string(), addopts),
+            ]),
+        );
+        assert!(config.is_test_class("HttpSuite"));
+        assert!(!config.is_test_class("TestHttp"));
+        assert!(config.is_test_function("test_http"));
+        assert_eq!(config.ignore, vec!["vendor", "legacy"]);
+        assert_eq!(config.ignore_glob, vec!["*_py2.py"]);
+    }
+
+    #[test]
+    fn test_load_for_args() {
+        let temp = TempProject::new(&[
+            ("project/tox.ini", "[pytest]\npython_files = check_*.py\n"),
+            ("project/tests/api/test_api.py", ""),
+            ("ci.ini", "[pytest]\ntestpaths = tests\n"),
+        ]);
+        temp.create_dir("project/tests/unit");
+        let root = temp.root.clone();
+        let project = root.join("project");
+
+        let paths = [
+            PathBuf::from("project/tests/unit"),
+            PathBuf::from("project/tests/api/test_api.py"),
+        ];
+        let config = Config::load_for_args(&root, &paths, None, None).unwrap();
+        assert_eq!(common_ancestor(&root, &paths), project.join("tests"));
+        assert_eq!(config.rootdir, project);
+        assert_eq!(config.python_files, vec!["check_*.py"]);
+
+        let config = Config::load_for_args(&project, &[], Some(Path::new("tests")), None).unwrap();
+        assert_eq!(config.rootdir, project.join("tests"));
+        assert_eq!(config.inifile, Some(project.join("tox.ini")));
+
+        let config =
+            Config::load_for_args(&project, &[], None, Some(Path::new("../ci.ini"))).unwrap();
+        assert_eq!(config.rootdir, root);
+        assert_eq!(config.testpaths, vec!["tests"]);
+        assert!(Config::load_for_args(&project, &[], None, Some(Path::new("no.ini"))).is_err());
+    }
+
+    #[test]
+    fn test_load_discovery() {
+        // tox.ini without a pytest section is passed over, setup.cfg above it is found
+        let temp = TempProject::new(&[
+            ("project/tests/tox.ini", "[tox]\nenvlist = py311\n"),
+            (
+                "project/setup.cfg",
+                "[tool:pytest]\npython_files = check_*.py\n",
+            ),
+        ]);
+        temp.create_dir("project/tests/unit");
+        let root = &temp.root;
+        let sub = root.join("project/tests/unit");
+        let config = Config::load(&sub).unwrap();
+        assert_eq!(config.rootdir, root.join("project"));
+        assert_eq!(config.inifile, Some(root.join("project/setup.cfg")));
+        assert_eq!(config.python_files, vec!["check_*.py"]);
+
+        // pytest.ini is the config file even without a section
+        temp.write("project/tests/pytest.ini", "");
+        let config = Config::load(&sub).unwrap();
+        assert_eq!(config.rootdir, root.join("project/tests"));
+        assert_eq!(config.python_files, Config::default().python_files);
+
+        // without config files setup.py tells the rootdir, the invocation dir otherwise
+        temp.create_dir("other/src/tests");
+        temp.write("other/setup.py", "");
+        let config = Config::load(&root.join("other/src/tests")).unwrap();
+        assert_eq!(config.rootdir, root.join("other"));
+        assert_eq!(config.inifile, None);
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let temp = TempProject::new(&[]);
+        for dir in [
+            "tests/unit",
+            "tests/api/unit",
+            "tests/.hidden/unit",
+            "src/unit",
+        ] {
+            temp.create_dir(dir);
+        }
+        let expand = |pattern| {
+            let paths: Vec<String> = expand_glob(&temp.root, pattern)
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            paths
+        };
+        assert_eq!(expand("tests/*/unit"), vec!["tests/api/unit"]);
+        assert_eq!(
+            expand("tests/**/unit"),
+            vec!["tests/unit", "tests/api/unit"]
+        );
+        assert_eq!(
+            expand("**/unit"),
+            vec!["src/unit", "tests/unit", "tests/api/unit"]
+        );
+        assert_eq!(
+            expand("tests/**"),
+            vec!["tests", "tests/api", "tests/api/unit", "tests/unit"]
+        );
+    }
+}
@@ -1,7 +1,352 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fixtures::FixtureIndex;
+use crate::parametrize::ParamIds;
+
 #[derive(Clone)]
 pub struct ParsedTest {
     pub test_name: String,
     pub row_location: usize,
+    pub end_row_location: usize,
+    pub kind: ItemKind,
     pub full_path: String,
+    pub parametrization: Option<Parametrization>,
+    /// file with the definition, differs from full_path for tests inherited from other modules
+    pub source_path: String,
+    /// names of the markers of the function, its classes and the module
+    pub markers: Vec<String>,
+    /// skip or xfail known before running the test
+    pub expected: Option<ExpectedOutcome>,
+    /// hash of the source of the test function, tells whether it changed since the last collection
+    pub source_hash: u64,
+    /// names of the fixtures the test function requests
+    pub fixtures: Vec<String>,
+}
+
+impl ParsedTest {
+    pub fn new(name: String, row_location: usize, filepath: &str) -> Self {
+        ParsedTest {
+            test_name: name.clone(),
+            row_location,
+            end_row_location: row_location,
+            kind: ItemKind::Function,
+            full_path: format!("{filepath}::{name}"),
+            parametrization: None,
+            source_path: filepath.to_string(),
+            markers: vec![],
+            expected: None,
+            source_hash: 0,
+            fixtures: vec![],
+        }
+    }
+
+    /// the test is skipped whatever the environment is
+    pub fn is_skipped(&self) -> bool {
+        matches!(self.expected, Some(ExpectedOutcome::Skip { .. }))
+    }
+}
+
+/// Node pytest collects the item as, class rows are items too
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemKind {
+    Function,
+    /// test method of a `unittest.TestCase` subclass
+    TestCaseFunction,
+    Class,
+    UnitTestCase,
 }
 
+impl ItemKind {
+    pub fn is_class(self) -> bool {
+        matches!(self, ItemKind::Class | ItemKind::UnitTestCase)
+    }
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ItemKind::Function => "Function",
+            ItemKind::TestCaseFunction => "TestCaseFunction",
+            ItemKind::Class => "Class",
+            ItemKind::UnitTestCase => "UnitTestCase",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Parametrization {
+    /// id generated by pytest.mark.parametrize, e.g. `1-a` for `test_x[1-a]`
+    Id(String),
+    /// parametrize values can't be worked out statically
+    Unresolved,
+}
+
+impl fmt::Display for Parametrization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parametrization::Id(id) => write!(f, "{id}"),
+            Parametrization::Unresolved => write!(f, "parametrized (unresolved)"),
+        }
+    }
+}
+
+/// Outcome worked out statically from skip and xfail marks.
+/// Conditions are kept as python source, they aren't evaluated
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExpectedOutcome {
+    /// `pytest.mark.skip` or a module level `pytest.skip(..., allow_module_level=True)`
+    Skip { reason: Option<String> },
+    /// `pytest.mark.skipif` or a module level `pytest.importorskip`
+    SkipIf {
+        condition: String,
+        reason: Option<String>,
+    },
+    XFail {
+        condition: Option<String>,
+        reason: Option<String>,
+        strict: bool,
+    },
+}
+
+impl ExpectedOutcome {
+    /// the one which decides the outcome when a test has several of them
+    pub fn strongest<'a>(
+        outcomes: impl IntoIterator<Item = &'a ExpectedOutcome>,
+    ) -> Option<&'a ExpectedOutcome> {
+        let rank = |outcome: &ExpectedOutcome| match outcome {
+            ExpectedOutcome::Skip { .. } => 0,
+            ExpectedOutcome::SkipIf { .. } => 1,
+            ExpectedOutcome::XFail { .. } => 2,
+        };
+        // min_by_key returns the first of equally ranked ones, the closest to the test
+        outcomes.into_iter().min_by_key(|outcome| rank(outcome))
+    }
+}
+
+impl fmt::Display for ExpectedOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            ExpectedOutcome::Skip { reason } => {
+                write!(f, "skip")?;
+                reason
+            }
+            ExpectedOutcome::SkipIf { condition, reason } => {
+                write!(f, "skipif {condition}")?;
+                reason
+            }
+            ExpectedOutcome::XFail {
+                condition,
+                reason,
+                strict,
+            } => {
+                write!(f, "xfail")?;
+                if *strict {
+                    write!(f, " strict")?;
+                }
+                if let Some(condition) = condition {
+                    write!(f, " if {condition}")?;
+                }
+                reason
+            }
+        };
+        if let Some(reason) = reason {
+            write!(f, ": {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of the last run, ordered from the least to the most important one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    NotRun,
+    Skipped,
+    XFail,
+    Passed,
+    XPass,
+    Failed,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+/// Test module which couldn't be collected, e.g. because of a syntax error
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CollectionError {
+    pub path: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Test-like item pytest doesn't collect, e.g. a test class with `__init__`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExcludedItem {
+    pub node_id: String,
+    pub row_location: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ExcludedItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.node_id, self.row_location, self.reason)
+    }
+}
+
+/// Result of walking the test paths: tests of every module that could be parsed
+/// and errors for the ones that couldn't
+#[derive(Default)]
+pub struct Collection {
+    pub tests: Vec<ParsedTest>,
+    pub errors: Vec<CollectionError>,
+    /// items which look like tests but are left out by pytest's rules
+    pub excluded: Vec<ExcludedItem>,
+    /// fixtures of the collected modules and their conftest.py files
+    pub fixtures: FixtureIndex,
+}
+
+/// Function or method definition as it was written in the module
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParsedFunction {
+    pub name: String,
+    pub row_location: usize,
+    pub end_row_location: usize,
+    /// hash of the source of the definition, decorators included
+    pub source_hash: u64,
+    /// parametrize marks in the order pytest applies them
+    pub parametrize: Vec<ParamIds>,
+    pub markers: Vec<String>,
+    /// skip and xfail marks, the closest to the definition first
+    pub expected: Vec<ExpectedOutcome>,
+    /// `function.__test__ = True/False` assigned in the module
+    pub dunder_test: Option<bool>,
+    /// arguments which are fixtures and `pytest.mark.usefixtures` names
+    pub fixtures: Vec<String>,
+}
+
+/// `@pytest.fixture` definition
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParsedFixture {
+    /// name tests request it by, `name=` of the decorator or the function name
+    pub name: String,
+    pub row_location: usize,
+    /// function, class, module, package or session
+    pub scope: String,
+    pub autouse: bool,
+    /// `params=` as written
+    pub params: Option<String>,
+    /// fixtures the fixture requests itself
+    pub requested: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParsedClass {
+    pub name: String,
+    pub row_location: usize,
+    pub end_row_location: usize,
+    /// dotted names of base classes as written, e.g. `mixins.BaseTests`
+    pub bases: Vec<String>,
+    pub parametrize: Vec<ParamIds>,
+    /// decorators and `pytestmark` of the class body
+    pub markers: Vec<String>,
+    pub expected: Vec<ExpectedOutcome>,
+    pub methods: Vec<ParsedFunction>,
+    /// classes defined in the class body
+    pub classes: Vec<ParsedClass>,
+    /// other names bound in the class body, they shadow inherited tests
+    pub attributes: Vec<ClassAttribute>,
+    /// `__test__ = True/False` of the class body
+    pub dunder_test: Option<bool>,
+    pub fixtures: Vec<ParsedFixture>,
+}
+
+/// Name bound in a class body by something else than a `def`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClassAttribute {
+    pub name: String,
+    pub row_location: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ModuleItem {
+    Function(ParsedFunction),
+    Class(ParsedClass),
+    /// name bound to something else than a function or a class
+    Assignment {
+        name: String,
+        row_location: usize,
+    },
+}
+
+impl ModuleItem {
+    pub fn name(&self) -> &str {
+        match self {
+            ModuleItem::Function(function) => &function.name,
+            ModuleItem::Class(class) => &class.name,
+            ModuleItem::Assignment { name, .. } => name,
+        }
+    }
+}
+
+/// Everything collection needs to know about a single python module
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ParsedModule {
+    pub path: String,
+    pub items: Vec<ModuleItem>,
+    /// local name -> imported dotted path, relative ones keep their leading dots
+    pub imports: HashMap<String, String>,
+    /// modules imported with `from x import *`
+    pub star_imports: Vec<String>,
+    /// markers of the module level `pytestmark`
+    pub markers: Vec<String>,
+    /// `pytestmark` skips, module level `pytest.skip` and `pytest.importorskip` calls
+    pub expected: Vec<ExpectedOutcome>,
+    pub fixtures: Vec<ParsedFixture>,
+}
+
+impl ParsedModule {
+    /// Binds the name like a module `__dict__` does: a rebound name keeps
+    /// the position of the first binding
+    pub fn bind(&mut self, item: ModuleItem) {
+        match self.items.iter().position(|i| i.name() == item.name()) {
+            Some(position) => self.items[position] = item,
+            None => self.items.push(item),
+        }
+    }
+
+    /// Finds the class by its name, dotted names like `TestOuter.TestInner` refer to nested classes
+    pub fn find_class(&self, name: &str) -> Option<&ParsedClass> {
+        let mut names = name.split('.');
+        let head = names.next()?;
+        let mut class = self.items.iter().rev().find_map(|item| match item {
+            ModuleItem::Class(class) if class.name == head => Some(class),
+            _ => None,
+        })?;
+        for name in names {
+            class = class.classes.iter().find(|c| c.name == name)?;
+        }
+        Some(class)
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{bail, Result};
+
+/// Boolean expression of pytest's `-k` and `-m` options:
+///
+/// ```text
+/// expression: expr? EOF
+/// expr:       and_expr ('or' and_expr)*
+/// and_expr:   not_expr ('and' not_expr)*
+/// not_expr:   'not' not_expr | '(' expr ')' | ident
+/// ```
+#[derive(Debug, PartialEq)]
+pub enum Expression {
+    Empty,
+    Ident(String),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Ident(String),
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || "_:+-.[]\\/".contains(c)
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::LeftParen),
+            ')' => tokens.push(Token::RightParen),
+            c if is_ident_char(c) => {
+                let mut ident = c.to_string();
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_ident_char(*c)) {
+                    ident.push(c);
+                }
+                tokens.push(match ident.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(ident),
+                });
+            }
+            c => bail!("unexpected character {c:?} at column {}", position + 1),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn accept(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Result<Expression> {
+        let mut expression = self.and_expr()?;
+        while self.accept(&Token::Or) {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and_expr()?));
+        }
+        Ok(expression)
+    }
+
+    fn and_expr(&mut self) -> Result<Expression> {
+        let mut expression = self.not_expr()?;
+        while self.accept(&Token::And) {
+            expression = Expression::And(Box::new(expression), Box::new(self.not_expr()?));
+        }
+        Ok(expression)
+    }
+
+    fn not_expr(&mut self) -> Result<Expression> {
+        if self.accept(&Token::Not) {
+            return Ok(Expression::Not(Box::new(self.not_expr()?)));
+        }
+        if self.accept(&Token::LeftParen) {
+            let expression = self.expr()?;
+            if !self.accept(&Token::RightParen) {
+                bail!("expected right parenthesis");
+            }
+            return Ok(expression);
+        }
+        match self.tokens.get(self.position) {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.position += 1;
+                Ok(Expression::Ident(ident))
+            }
+            Some(token) => bail!("unexpected {token:?}, expected not, ( or identifier"),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(input: &str) -> Result<Expression> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Expression::Empty);
+        }
+        let expression = parser.expr()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            bail!("unexpected {token:?} after the end of expression");
+        }
+        Ok(expression)
+    }
+
+    /// Evaluates the expression, identifiers are checked with the matcher.
+    /// Like in pytest, an empty expression matches nothing
+    pub fn evaluate(&self, matcher: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            Expression::Empty => false,
+            Expression::Ident(ident) => matcher(ident),
+            Expression::Not(e) => !e.evaluate(matcher),
+            Expression::And(left, right) => left.evaluate(matcher) && right.evaluate(matcher),
+            Expression::Or(left, right) => left.evaluate(matcher) || right.evaluate(matcher),
+        }
+    }
+}
+
+/// pytest's `-k` matching: case insensitive substring of any of the names
+pub fn keyword_matches(expression: &Expression, names: &[&str]) -> bool {
+    let names: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+    expression.evaluate(&|ident: &str| {
+        let ident = ident.to_lowercase();
+        names.iter().any(|name| name.contains(&ident))
+    })
+}
+
+/// pytest's `-m` matching: marker names are compared exactly
+pub fn marker_matches(expression: &Expression, markers: &[String]) -> bool {
+    expression.evaluate(&|ident: &str| markers.iter().any(|marker| marker == ident))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let expression = Expression::parse("http and not (slow or test_x[1-a])").unwrap();
+        assert_eq!(
+            expression,
+            Expression::And(
+                Box::new(Expression::Ident("http".to_string())),
+                Box::new(Expression::Not(Box::new(Expression::Or(
+                    Box::new(Expression::Ident("slow".to_string())),
+                    Box::new(Expression::Ident("test_x[1-a]".to_string())),
+                ))))
+            )
+        );
+        assert!(Expression::parse("http and").is_err());
+        assert!(Expression::parse("(http").is_err());
+        assert!(Expression::parse("http slow").is_err());
+        assert!(Expression::parse("a = b").is_err());
+        assert_eq!(Expression::parse("  ").unwrap(), Expression::Empty);
+    }
+
+    #[test]
+    fn test_keyword_matches() {
+        let names = ["tests/test_http.py", "TestClient", "test_get[1-a]"];
+        let matches = |input: &str| keyword_matches(&Expression::parse(input).unwrap(), &names);
+        assert!(matches("http and client"));
+        assert!(matches("GET and not slow"));
+        assert!(!matches("http and not client"));
+        assert!(matches("slow or test_get[1"));
+    }
+
+    #[test]
+    fn test_marker_matches() {
+        let markers = vec!["slow".to_string(), "network".to_string()];
+        let matches = |input: &str| marker_matches(&Expression::parse(input).unwrap(), &markers);
+        assert!(matches("slow and not windows"));
+        assert!(!matches("slo"));
+        assert!(!matches("not network"));
+    }
+}
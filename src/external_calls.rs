@@ -1,17 +1,20 @@
-use crate::entities::ParsedTest;
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::process::Child;
 use std::process::Command;
-use std::process::Output;
 use std::process::Stdio;
 
 #[cfg(target_os = "linux")]
 pub fn run_command_in_shell(command: &str) -> Result<()> {
-    if let Err(_) = Command::new("gnome-terminal")
+    if Command::new("gnome-terminal")
         .arg("--version")
         .stdout(Stdio::null())
         .status()
+        .is_err()
     {
         bail!("Not implemented for your terminal")
     }
@@ -19,14 +22,14 @@ pub fn run_command_in_shell(command: &str) -> Result<()> {
     let output = Command::new("gnome-terminal")
         .arg("--title=newWindow")
         .arg("--")
-        .arg(format!("{}", shell))
+        .arg(shell)
         .arg("-c")
         .arg(command)
         .output()?;
     match output.stderr.is_empty() {
         true => Ok(()),
         false => {
-            let error: String = String::from_utf8_lossy(&output.stderr).try_into()?;
+            let error: String = String::from_utf8_lossy(&output.stderr).into();
             bail!(error)
         }
     }
@@ -46,7 +49,7 @@ pub fn run_command_in_shell(command: &str) -> Result<()> {
     match output.stderr.is_empty() {
         true => Ok(()),
         false => {
-            let error: String = String::from_utf8_lossy(&output.stderr).try_into()?;
+            let error: String = String::from_utf8_lossy(&output.stderr).into();
             bail!(error)
         }
     }
@@ -57,33 +60,88 @@ pub fn run_command_in_shell(command: &str) -> Result<()> {
     bail!("Not implemented for your os")
 }
 
-pub fn run_test(test_name: String) -> Output {
-    let output = Command::new("pytest")
-        .arg(test_name)
+/// Quotes the argument for posix shells, node ids may contain `[`, spaces and quotes
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Puts the text into the system clipboard with the first available tool
+#[cfg(target_os = "linux")]
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    let tools: [&[&str]; 3] = [
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+    for tool in tools {
+        if pipe_to_command(tool, text).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!("Install wl-copy, xclip or xsel to copy node ids")
+}
+
+#[cfg(target_os = "macos")]
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    pipe_to_command(&["pbcopy"], text)
+}
+
+#[cfg(target_os = "windows")]
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    pipe_to_command(&["clip"], text)
+}
+
+fn pipe_to_command(command: &[&str], input: &str) -> Result<()> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    match child.wait()?.success() {
+        true => Ok(()),
+        false => bail!("{} failed", command[0]),
+    }
+}
+
+pub fn spawn_tests(node_ids: &[String], options: &[String], junit_report: &Path) -> Result<Child> {
+    let mut command = Command::new("pytest");
+    // own process group, so interrupting it reaches the processes pytest starts too
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command
+        .args(node_ids)
+        .args(options)
+        .arg(format!("--junitxml={}", junit_report.display()))
         .arg("-vvv")
         .arg("-p")
         .arg("no:warnings")
         .env("PYTEST_ADDOPTS", "--color=yes")
-        .output()
-        .expect("failed to execute process");
-    output
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to execute pytest")?;
+    Ok(child)
 }
 
 #[cfg(target_os = "linux")]
-pub fn open_editor(test: &ParsedTest) -> Result<()> {
-    let file = test.full_path.split("::").next().unwrap();
+pub fn open_editor(file: &str, line: usize) -> Result<()> {
     let editor = env::var("EDITOR")?;
     let command: String;
     if editor.as_str().contains("hx") {
-        command = format!("${} {}:{}", "EDITOR", file, test.row_location)
+        command = format!("${} {}:{}", "EDITOR", file, line)
     } else if editor.as_str().contains("vi") {
-        command = format!("${} {} +{}", "EDITOR", file, test.row_location)
+        command = format!("${} {} +{}", "EDITOR", file, line)
     } else if editor.as_str().contains("nano") {
-        command = format!("${} +{} {}", "EDITOR", test.row_location, file)
+        command = format!("${} +{} {}", "EDITOR", line, file)
     } else if editor.as_str().contains("code") {
-        command = format!("${} -g {}:{}", "EDITOR", file, test.row_location)
+        command = format!("${} -g {}:{}", "EDITOR", file, line)
     } else if editor.as_str().contains("pycharm") {
-        command = format!("${} -line {} {}", "EDITOR", test.row_location, file)
+        command = format!("${} -line {} {}", "EDITOR", line, file)
     } else {
         command = format!("${} {}", "EDITOR", file)
     };
@@ -92,13 +150,12 @@ pub fn open_editor(test: &ParsedTest) -> Result<()> {
 }
 
 #[cfg(target_os = "macos")]
-pub fn open_editor(test: &ParsedTest) -> Result<()> {
-    let file = test.full_path.split("::").next().unwrap();
+pub fn open_editor(file: &str, _line: usize) -> Result<()> {
     Command::new("open").arg("-t").arg(file).output()?;
     Ok(())
 }
 
 #[cfg(target_os = "windows")]
-pub fn open_editor(test: &ParsedTest) -> Result<()> {
+pub fn open_editor(_file: &str, _line: usize) -> Result<()> {
     bail!("Not implemented for your os")
 }
@@ -0,0 +1,214 @@
+/// How the text of the filter box is matched against tests
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    /// every space separated token is a substring of the node id
+    Exact,
+    /// fzf-like subsequence matching, the best matches go first
+    Fuzzy,
+    /// pytest `-k` expression
+    Keyword,
+    /// pytest `-m` expression
+    Marker,
+}
+
+impl FilterMode {
+    pub fn next(self) -> FilterMode {
+        match self {
+            FilterMode::Exact => FilterMode::Fuzzy,
+            FilterMode::Fuzzy => FilterMode::Keyword,
+            FilterMode::Keyword => FilterMode::Marker,
+            FilterMode::Marker => FilterMode::Exact,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterMode::Exact => "exact",
+            FilterMode::Fuzzy => "fuzzy",
+            FilterMode::Keyword => "-k",
+            FilterMode::Marker => "-m",
+        }
+    }
+
+    /// pytest option the filter is passed with when the filtered tests are run
+    pub fn pytest_option(self) -> Option<&'static str> {
+        match self {
+            FilterMode::Keyword | FilterMode::Marker => Some(self.name()),
+            FilterMode::Exact | FilterMode::Fuzzy => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FilterMatch {
+    pub score: i64,
+    /// indices of the matched chars, used for highlighting
+    pub positions: Vec<usize>,
+}
+
+pub fn exact_match(tokens: &[String], text: &str) -> Option<FilterMatch> {
+    let mut positions = vec![];
+    for token in tokens.iter().filter(|t| !t.is_empty()) {
+        let start = text.find(token.as_str())?;
+        let start = text[..start].chars().count();
+        positions.extend(start..start + token.chars().count());
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    Some(FilterMatch {
+        score: 0,
+        positions,
+    })
+}
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Every space separated token has to match as a subsequence, scores are summed up.
+/// The query is case sensitive only when it has upper case chars
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FilterMatch> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let text: Vec<char> = text.chars().collect();
+    let mut result = FilterMatch {
+        score: 0,
+        positions: vec![],
+    };
+    for token in query.split_whitespace() {
+        let token: Vec<char> = token.chars().collect();
+        let token_match = fuzzy_match_token(&token, &text, case_sensitive)?;
+        result.score += token_match.score;
+        result.positions.extend(token_match.positions);
+    }
+    result.positions.sort_unstable();
+    result.positions.dedup();
+    Some(result)
+}
+
+fn bonus(text: &[char], index: usize) -> i64 {
+    let Some(previous) = index.checked_sub(1).map(|i| text[i]) else {
+        return BONUS_BOUNDARY;
+    };
+    let current = text[index];
+    match previous {
+        '/' | '_' | ':' | '.' | '-' | '[' | ' ' => BONUS_BOUNDARY,
+        p if p.is_lowercase() && current.is_uppercase() => BONUS_CAMEL_CASE,
+        p if !p.is_ascii_digit() && current.is_ascii_digit() => BONUS_CAMEL_CASE,
+        _ => 0,
+    }
+}
+
+/// Finds the best scored alignment of the token in the text, a simplified version of
+/// the fzf algorithm: matches after separators and consecutive matches score more
+/// and gaps between matched chars cost something
+fn fuzzy_match_token(token: &[char], text: &[char], case_sensitive: bool) -> Option<FilterMatch> {
+    if token.is_empty() {
+        return Some(FilterMatch {
+            score: 0,
+            positions: vec![],
+        });
+    }
+    let equal = |a: char, b: char| match case_sensitive {
+        true => a == b,
+        false => a.to_lowercase().eq(b.to_lowercase()),
+    };
+    let (n, m) = (token.len(), text.len());
+    if n > m {
+        return None;
+    }
+    // scores[j]: best score of token[..=i] with token[i] matched at text[j], only the
+    // row of the previous token is kept
+    let mut scores: Vec<Option<i64>> = vec![None; m];
+    let mut row: Vec<Option<i64>> = vec![None; m];
+    // previous[i * m + j]: position token[i - 1] is matched at in the best alignment
+    let mut previous: Vec<usize> = vec![0; n * m];
+    for j in 0..m {
+        if equal(token[0], text[j]) {
+            scores[j] = Some(SCORE_MATCH + bonus(text, j) * BONUS_FIRST_CHAR_MULTIPLIER);
+        }
+    }
+    for i in 1..n {
+        row.fill(None);
+        // best score of token[..i] ending before j - 1 with the gap penalty up to j
+        let mut gap_best: Option<(i64, usize)> = None;
+        for j in i..m {
+            if j >= 2 {
+                if let Some(score) = scores[j - 2] {
+                    let started = (score + GAP_START, j - 2);
+                    gap_best = match gap_best {
+                        Some((best, k)) if best + GAP_EXTENSION >= started.0 => {
+                            Some((best + GAP_EXTENSION, k))
+                        }
+                        _ => Some(started),
+                    };
+                } else if let Some((best, k)) = gap_best {
+                    gap_best = Some((best + GAP_EXTENSION, k));
+                }
+            }
+            if !equal(token[i], text[j]) {
+                continue;
+            }
+            let consecutive = scores[j - 1].map(|s| (s + BONUS_CONSECUTIVE, j - 1));
+            let best = match (consecutive, gap_best) {
+                (Some(c), Some(g)) if g.0 > c.0 => Some(g),
+                (Some(c), _) => Some(c),
+                (None, g) => g,
+            };
+            if let Some((score, k)) = best {
+                row[j] = Some(score + SCORE_MATCH + bonus(text, j));
+                previous[i * m + j] = k;
+            }
+        }
+        std::mem::swap(&mut scores, &mut row);
+    }
+    let (score, mut j) = (0..m)
+        .filter_map(|j| scores[j].map(|s| (s, j)))
+        .max_by_key(|(s, j)| (*s, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![j];
+    for i in (1..n).rev() {
+        j = previous[i * m + j];
+        positions.push(j);
+    }
+    positions.reverse();
+    Some(FilterMatch { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let path = "tests/test_client_response.py::test_read";
+        let found = fuzzy_match("tcliresp", path).unwrap();
+        let matched: String = found
+            .positions
+            .iter()
+            .map(|i| path.chars().nth(*i).unwrap())
+            .collect();
+        assert_eq!(matched, "tcliresp");
+        // the matches start at word boundaries
+        assert_eq!(found.positions[..4], [6, 11, 12, 13]);
+
+        assert!(fuzzy_match("tcliresp", "tests/test_cookiejar.py::test_loose").is_none());
+        assert!(fuzzy_match("Client", path).is_none());
+
+        let weaker = fuzzy_match("tcliresp", "tests/test_cli.py::test_render_escape").unwrap();
+        assert!(found.score > weaker.score);
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let tokens = vec!["client".to_string(), "read".to_string()];
+        let found = exact_match(&tokens, "tests/test_client.py::test_read").unwrap();
+        assert_eq!(
+            found.positions,
+            vec![11, 12, 13, 14, 15, 16, 27, 28, 29, 30]
+        );
+        assert!(exact_match(&tokens, "tests/test_client.py::test_write").is_none());
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rustpython_parser::ast;
+
+use crate::entities::{ModuleItem, ParsedClass, ParsedFixture, ParsedModule, ParsedTest};
+use crate::markers;
+use crate::parametrize::{dotted_name, keyword, ModuleScope};
+
+/// Fixtures pytest provides itself
+const BUILTIN_FIXTURES: [&str; 18] = [
+    "cache",
+    "capfd",
+    "capfdbinary",
+    "caplog",
+    "capsys",
+    "capsysbinary",
+    "doctest_namespace",
+    "monkeypatch",
+    "pytestconfig",
+    "record_property",
+    "record_testsuite_property",
+    "record_xml_attribute",
+    "recwarn",
+    "request",
+    "tmp_path",
+    "tmp_path_factory",
+    "tmpdir",
+    "tmpdir_factory",
+];
+
+/// Fixture defined by a `@pytest.fixture` decorator, None for other decorators
+pub fn parse(
+    function_name: &str,
+    decorators: &[ast::Expr],
+    requested: &[String],
+    row_location: usize,
+    scope: &ModuleScope,
+    source: &str,
+) -> Option<ParsedFixture> {
+    let decorator = decorators.iter().find(|d| is_fixture_decorator(d, scope))?;
+    let keywords: &[ast::Keyword] = match &decorator.node {
+        ast::ExprKind::Call { keywords, .. } => keywords,
+        _ => &[],
+    };
+    let string = |name| match keyword(keywords, name).map(|e| &e.node) {
+        Some(ast::ExprKind::Constant {
+            value: ast::Constant::Str(value),
+            ..
+        }) => Some(value.clone()),
+        _ => None,
+    };
+    Some(ParsedFixture {
+        name: string("name").unwrap_or_else(|| function_name.to_string()),
+        row_location,
+        scope: string("scope").unwrap_or_else(|| "function".to_string()),
+        autouse: matches!(
+            keyword(keywords, "autouse").map(|e| &e.node),
+            Some(ast::ExprKind::Constant {
+                value: ast::Constant::Bool(true),
+                ..
+            })
+        ),
+        params: keyword(keywords, "params").and_then(|e| markers::source_segment(source, e)),
+        requested: requested.to_vec(),
+    })
+}
+
+/// `pytest.fixture`, `fixture` imported from pytest or `pytest_asyncio.fixture`,
+/// called or not
+fn is_fixture_decorator(decorator: &ast::Expr, scope: &ModuleScope) -> bool {
+    let func = match &decorator.node {
+        ast::ExprKind::Call { func, .. } => func,
+        _ => decorator,
+    };
+    matches!(
+        dotted_name(scope.resolve(func)).as_deref(),
+        Some("pytest.fixture" | "fixture" | "pytest_asyncio.fixture")
+    )
+}
+
+/// Fixture with the file and class it's defined in
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixtureDefinition {
+    pub fixture: ParsedFixture,
+    pub path: String,
+    /// dotted name of the class, None for module level fixtures
+    pub class: Option<String>,
+}
+
+/// Fixtures of the test modules and of the conftest.py files next to them
+/// and in the directories above
+#[derive(Clone, Debug, Default)]
+pub struct FixtureIndex {
+    files: HashMap<String, Vec<FixtureDefinition>>,
+}
+
+/// Fixture in the chain of a test, `depth` is 0 for the ones the test requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedFixture<'a> {
+    pub name: String,
+    pub depth: usize,
+    pub definition: Option<&'a FixtureDefinition>,
+}
+
+impl ResolvedFixture<'_> {
+    pub fn is_builtin(&self) -> bool {
+        self.definition.is_none() && BUILTIN_FIXTURES.contains(&self.name.as_str())
+    }
+}
+
+impl FixtureIndex {
+    pub fn insert(&mut self, module: &ParsedModule) {
+        let mut definitions: Vec<FixtureDefinition> = module
+            .fixtures
+            .iter()
+            .map(|fixture| FixtureDefinition {
+                fixture: fixture.clone(),
+                path: module.path.clone(),
+                class: None,
+            })
+            .collect();
+        for item in &module.items {
+            if let ModuleItem::Class(class) = item {
+                class_fixtures(&module.path, class, None, &mut definitions);
+            }
+        }
+        self.files.insert(module.path.clone(), definitions);
+    }
+
+    /// Forgets the fixtures of a removed file
+    pub fn remove(&mut self, path: &str) {
+        self.files.insert(path.to_string(), vec![]);
+    }
+
+    /// Replaces the fixtures of the files the other index has
+    pub fn update(&mut self, other: FixtureIndex) {
+        self.files.extend(other.files);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Fixtures pytest sets up for the test, in a depth first order: autouse
+    /// fixtures, the requested ones and, indented, the fixtures they request.
+    /// Fixtures which were listed already aren't followed again
+    pub fn resolve(&self, test: &ParsedTest) -> Vec<ResolvedFixture<'_>> {
+        let mut segments: Vec<&str> = test.full_path.split("::").collect();
+        segments.pop();
+        let module = segments.remove(0);
+        let levels = levels(module, &segments);
+        let mut chain = vec![];
+        let mut autouse = vec![];
+        // autouse fixtures of the outermost levels are set up first
+        for level in levels.iter().rev() {
+            for definition in self.definitions(level) {
+                if definition.fixture.autouse && !autouse.contains(&definition.fixture.name) {
+                    autouse.push(definition.fixture.name.clone());
+                }
+            }
+        }
+        let mut seen = vec![];
+        for name in autouse.iter().chain(&test.fixtures) {
+            self.resolve_name(name, &levels, 0, 0, &mut seen, &mut chain);
+        }
+        chain
+    }
+
+    fn resolve_name<'a>(
+        &'a self,
+        name: &str,
+        levels: &[Level],
+        start: usize,
+        depth: usize,
+        seen: &mut Vec<(String, usize)>,
+        chain: &mut Vec<ResolvedFixture<'a>>,
+    ) {
+        let found = levels
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find_map(|(i, level)| {
+                self.definitions(level)
+                    .find(|d| d.fixture.name == name)
+                    .map(|d| (i, d))
+            });
+        chain.push(ResolvedFixture {
+            name: name.to_string(),
+            depth,
+            definition: found.map(|(_, d)| d),
+        });
+        let Some((level, definition)) = found else {
+            return;
+        };
+        if seen.contains(&(name.to_string(), level)) {
+            return;
+        }
+        seen.push((name.to_string(), level));
+        for requested in &definition.fixture.requested {
+            // a fixture requesting its own name gets the one it overrides
+            let start = match requested == name {
+                true => level + 1,
+                false => level,
+            };
+            self.resolve_name(requested, levels, start, depth + 1, seen, chain);
+        }
+    }
+
+    fn definitions<'a>(&'a self, level: &Level) -> impl Iterator<Item = &'a FixtureDefinition> {
+        let class = level.class.clone();
+        self.files
+            .get(&level.path)
+            .into_iter()
+            .flatten()
+            .filter(move |d| d.class == class)
+    }
+}
+
+fn class_fixtures(
+    path: &str,
+    class: &ParsedClass,
+    outer: Option<&str>,
+    definitions: &mut Vec<FixtureDefinition>,
+) {
+    let name = match outer {
+        Some(outer) => format!("{outer}.{}", class.name),
+        None => class.name.clone(),
+    };
+    definitions.extend(class.fixtures.iter().map(|fixture| FixtureDefinition {
+        fixture: fixture.clone(),
+        path: path.to_string(),
+        class: Some(name.clone()),
+    }));
+    for nested in &class.classes {
+        class_fixtures(path, nested, Some(&name), definitions);
+    }
+}
+
+/// Place fixtures are looked up in, from the closest to the test
+struct Level {
+    path: String,
+    class: Option<String>,
+}
+
+/// Classes of the test from the innermost, the module, then the conftest.py
+/// files of the module's directory and the ones above
+fn levels(module: &str, classes: &[&str]) -> Vec<Level> {
+    let mut levels = vec![];
+    for i in (1..=classes.len()).rev() {
+        levels.push(Level {
+            path: module.to_string(),
+            class: Some(classes[..i].join(".")),
+        });
+    }
+    levels.push(Level {
+        path: module.to_string(),
+        class: None,
+    });
+    for dir in Path::new(module).ancestors().skip(1) {
+        levels.push(Level {
+            path: dir.join("conftest.py").to_string_lossy().to_string(),
+            class: None,
+        });
+    }
+    levels
+}
+
+/// conftest.py files which can provide fixtures to the modules
+pub fn conftest_paths<'p>(modules: impl IntoIterator<Item = &'p Path>) -> Vec<String> {
+    let mut paths = vec![];
+    for module in modules {
+        for level in levels(&module.to_string_lossy(), &[]).into_iter().skip(1) {
+            if !paths.contains(&level.path) && Path::new(&level.path).is_file() {
+                paths.push(level.path);
+            }
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module_source;
+    use crate::target::Target;
+
+    #[test]
+    fn test_resolve() {
+        let conftest = r#"
+import pytest
+
+@pytest.fixture(scope="session", params=[1, 2])
+def db():
+    pass
+
+@pytest.fixture
+def client(db, tmp_path):
+    pass
+
+@pytest.fixture(autouse=True)
+def reset():
+    pass
+"#;
+        let module = r#"
+import pytest
+
+@pytest.fixture
+def client(client):
+    pass
+
+class TestApi:
+    @pytest.fixture(name="user")
+    def make_user(self, db):
+        pass
+
+    def test_get(self, client, user, unknown):
+        pass
+"#;
+        let mut index = FixtureIndex::default();
+        let target = Target::default();
+        index.insert(&parse_module_source(conftest, "tests/conftest.py", &target).unwrap());
+        let module = parse_module_source(module, "tests/api/test_api.py", &target).unwrap();
+        index.insert(&module);
+        let class = module.find_class("TestApi").unwrap();
+        assert_eq!(class.methods[1].fixtures, vec!["client", "user", "unknown"]);
+
+        let mut test = ParsedTest::new("TestApi::test_get".to_string(), 13, &module.path);
+        test.fixtures = class.methods[1].fixtures.clone();
+        let chain = index.resolve(&test);
+        let rows: Vec<String> = chain
+            .iter()
+            .map(|f| {
+                let location = f
+                    .definition
+                    .map(|d| format!(" {}:{}", d.path, d.fixture.row_location))
+                    .unwrap_or_default();
+                format!("{}{}{location}", "  ".repeat(f.depth), f.name)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                "reset tests/conftest.py:13",
+                "client tests/api/test_api.py:5",
+                "  client tests/conftest.py:9",
+                "    db tests/conftest.py:5",
+                "    tmp_path",
+                "user tests/api/test_api.py:10",
+                "  db tests/conftest.py:5",
+                "unknown",
+            ]
+        );
+        assert!(chain[4].is_builtin());
+        assert!(!chain[7].is_builtin());
+        let fixture = &chain[3].definition.unwrap().fixture;
+        assert_eq!(fixture.scope, "session");
+        assert_eq!(fixture.params.as_deref(), Some("[1, 2]"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::entities::{Collection, CollectionError, ItemKind, Parametrization, ParsedTest};
+
+/// Machine readable output of the collected tests
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// object with the tests and the collection errors
+    Json,
+    /// an object per test and line
+    Jsonl,
+    /// node ids one per line, they can be passed to pytest
+    Nodeids,
+    /// `<Module>`, `<Class>` and `<Function>` tree like `pytest --collect-only` prints
+    PytestTree,
+}
+
+/// Test as the structured formats describe it
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestRecord<'a> {
+    pub node_id: &'a str,
+    /// module the test is collected from
+    pub file: &'a str,
+    /// module with the definition, lines refer to it
+    pub source_file: &'a str,
+    /// dotted name of the class, e.g. `TestOuter.TestInner`
+    pub class: Option<String>,
+    pub function: &'a str,
+    pub line: usize,
+    pub end_line: usize,
+    pub markers: &'a [String],
+    /// id of a parametrized test, e.g. `1-a` of `test_x[1-a]`
+    pub param_id: Option<&'a str>,
+    /// parametrize values can't be worked out statically
+    pub unresolved_params: bool,
+}
+
+impl<'a> TestRecord<'a> {
+    pub fn new(test: &'a ParsedTest) -> Self {
+        let mut names: Vec<&str> = node_path(test).split("::").collect();
+        let file = names.remove(0);
+        let function = names.pop().unwrap_or_default();
+        let param_id = match &test.parametrization {
+            Some(Parametrization::Id(id)) => Some(id.as_str()),
+            _ => None,
+        };
+        TestRecord {
+            node_id: &test.full_path,
+            file,
+            source_file: &test.source_path,
+            class: (!names.is_empty()).then(|| names.join(".")),
+            function,
+            line: test.row_location,
+            end_line: test.end_row_location,
+            markers: &test.markers,
+            param_id,
+            unresolved_params: test.parametrization == Some(Parametrization::Unresolved),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    tests: Vec<TestRecord<'a>>,
+    errors: &'a [CollectionError],
+}
+
+/// Writes the collected tests, class rows aren't tests and are left out but
+/// by the tree. Only the json object holds the collection errors
+pub fn write(format: Format, collection: &Collection, out: &mut impl Write) -> io::Result<()> {
+    let tests = || collection.tests.iter().filter(|t| !t.kind.is_class());
+    match format {
+        Format::Json => {
+            let output = JsonOutput {
+                tests: tests().map(TestRecord::new).collect(),
+                errors: &collection.errors,
+            };
+            serde_json::to_writer_pretty(&mut *out, &output)?;
+            writeln!(out)
+        }
+        Format::Jsonl => {
+            for test in tests() {
+                serde_json::to_writer(&mut *out, &TestRecord::new(test))?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        Format::Nodeids => {
+            for test in tests() {
+                writeln!(out, "{}", test.full_path)?;
+            }
+            Ok(())
+        }
+        Format::PytestTree => write_tree(&collection.tests, out),
+    }
+}
+
+/// Layout of pytest 6 like in `tests/pytest_aiohttp_collection.txt`: tests of classes
+/// are under an `Instance` node which isn't printed but indents them, unittest test
+/// cases don't have it. Docstrings aren't printed
+fn write_tree(tests: &[ParsedTest], out: &mut impl Write) -> io::Result<()> {
+    let mut module = "";
+    // class node ids -> indent of their tests
+    let mut classes: HashMap<&str, usize> = HashMap::new();
+    for test in tests {
+        let Some((parent, _)) = node_path(test).rsplit_once("::") else {
+            continue;
+        };
+        let path = parent.split("::").next().unwrap_or_default();
+        if path != module {
+            writeln!(out, "<Module {path}>")?;
+            module = path;
+            classes.clear();
+        }
+        let indent = classes.get(parent).copied().unwrap_or(1);
+        let name = &test.full_path[parent.len() + 2..];
+        writeln!(out, "{}<{} {name}>", "  ".repeat(indent), test.kind)?;
+        match test.kind {
+            ItemKind::Class => classes.insert(&test.full_path, indent + 2),
+            ItemKind::UnitTestCase => classes.insert(&test.full_path, indent + 1),
+            _ => None,
+        };
+    }
+    Ok(())
+}
+
+/// Node id without the parametrize id, which may contain `::` itself
+pub fn node_path(test: &ParsedTest) -> &str {
+    match &test.parametrization {
+        Some(Parametrization::Id(id)) => test
+            .full_path
+            .strip_suffix(&format!("[{id}]"))
+            .unwrap_or(&test.full_path),
+        _ => &test.full_path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn test_formats() {
+        let source = r#"
+import unittest
+
+import pytest
+
+
+@pytest.mark.slow
+@pytest.mark.parametrize("x", ["a::b", 2])
+def test_param(x):
+    pass
+
+
+class TestOuter:
+    class TestInner:
+        def test_inner(self):
+            assert True
+
+    def test_outer(self):
+        pass
+
+
+class Case(unittest.TestCase):
+    def test_case(self):
+        pass
+"#;
+        let collection = parser::collect_source(source, "test_formats.py").unwrap();
+        let output = |format| {
+            let mut out = vec![];
+            write(format, &collection, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            output(Format::PytestTree),
+            "<Module test_formats.py>
+  <Function test_param[a::b]>
+  <Function test_param[2]>
+  <Class TestOuter>
+      <Class TestInner>
+          <Function test_inner>
+      <Function test_outer>
+  <UnitTestCase Case>
+    <TestCaseFunction test_case>
+"
+        );
+        assert_eq!(
+            output(Format::Nodeids).lines().collect::<Vec<_>>(),
+            vec![
+                "test_formats.py::test_param[a::b]",
+                "test_formats.py::test_param[2]",
+                "test_formats.py::TestOuter::TestInner::test_inner",
+                "test_formats.py::TestOuter::test_outer",
+                "test_formats.py::Case::test_case",
+            ]
+        );
+        let jsonl = output(Format::Jsonl);
+        let records: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            records[0],
+            serde_json::json!({
+                "node_id": "test_formats.py::test_param[a::b]",
+                "file": "test_formats.py",
+                "source_file": "test_formats.py",
+                "class": null,
+                "function": "test_param",
+                "line": 9,
+                "end_line": 10,
+                "markers": ["slow", "parametrize"],
+                "param_id": "a::b",
+                "unresolved_params": false,
+            })
+        );
+        assert_eq!(records[2]["class"], "TestOuter.TestInner");
+        assert_eq!(records[2]["line"], 15);
+        assert_eq!(records[2]["end_line"], 16);
+        let json: serde_json::Value = serde_json::from_str(&output(Format::Json)).unwrap();
+        assert_eq!(json["tests"].as_array().unwrap().len(), 5);
+        assert_eq!(json["errors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_tree_of_selected_node_id() {
+        let source = r#"
+class TestA:
+    def test_x(self):
+        pass
+
+    def test_y(self):
+        pass
+
+
+def test_z():
+    pass
+"#;
+        let mut collection = parser::collect_source(source, "f.py").unwrap();
+        parser::select_node_ids(&mut collection.tests, &["f.py::TestA::test_x".to_string()]);
+        let mut out = vec![];
+        write(Format::PytestTree, &collection, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<Module f.py>
+  <Class TestA>
+      <Function test_x>
+"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use rustpython_parser::ast;
+use rustpython_parser::parse_program;
+
+use crate::config::{fnmatch, Config};
+use crate::parametrize::dotted_name;
+use crate::parser::nested_bodies;
+use crate::target::Target;
+
+/// Paths collection leaves out: `--ignore` and `--ignore-glob` of `addopts`,
+/// `collect_ignore` and `collect_ignore_glob` of the conftest.py files on the way,
+/// and the ones the `.gitignore` and `.ignore` files on the way match
+pub struct Ignores {
+    /// absolute paths
+    paths: Vec<PathBuf>,
+    /// patterns matched against absolute paths, `*` matches `/` too
+    globs: Vec<String>,
+    /// `.gitignore` and `.ignore` files of a directory, patterns of `.ignore` win
+    gitignores: Vec<Gitignore>,
+    invocation_dir: PathBuf,
+    /// files above the rootdir aren't read, like conftest.py files with pytest's confcutdir
+    rootdir: PathBuf,
+    target: Target,
+    /// directories whose files have been read
+    read: HashSet<PathBuf>,
+}
+
+impl Ignores {
+    pub fn new(config: &Config) -> Self {
+        let invocation_dir = std::env::current_dir().unwrap_or_default();
+        Ignores {
+            paths: config
+                .ignore
+                .iter()
+                .map(|path| absolute(&invocation_dir, Path::new(path)))
+                .collect(),
+            // like pytest, the patterns are relative to the invocation dir
+            globs: config
+                .ignore_glob
+                .iter()
+                .map(|glob| invocation_dir.join(glob).to_string_lossy().to_string())
+                .collect(),
+            gitignores: vec![],
+            rootdir: absolute(&invocation_dir, &config.rootdir),
+            invocation_dir,
+            target: config.target.clone(),
+            read: HashSet::new(),
+        }
+    }
+
+    /// Reads the ignore files and the conftest.py lists of the directory and the ones above
+    pub fn read_dirs(&mut self, dir: &Path) {
+        let dir = absolute(&self.invocation_dir, dir);
+        for dir in dir.ancestors() {
+            // the ones above have been read as well when the dir has
+            if !dir.starts_with(&self.rootdir) || !self.read.insert(dir.to_path_buf()) {
+                break;
+            }
+            self.read_conftest(dir);
+            self.read_gitignores(dir);
+        }
+    }
+
+    fn read_conftest(&mut self, dir: &Path) {
+        let Ok(contents) = std::fs::read_to_string(dir.join("conftest.py")) else {
+            return;
+        };
+        // pytest fails on conftest.py files which don't parse, collection reports it
+        let Ok(program) = parse_program(&contents, "<embedded>") else {
+            return;
+        };
+        let mut lists = CollectIgnore::default();
+        lists.read(&program, &self.target);
+        self.paths.extend(
+            lists
+                .paths
+                .iter()
+                .map(|path| absolute(dir, Path::new(path))),
+        );
+        self.globs.extend(
+            lists
+                .globs
+                .iter()
+                .map(|glob| dir.join(glob).to_string_lossy().to_string()),
+        );
+    }
+
+    fn read_gitignores(&mut self, dir: &Path) {
+        let files: Vec<PathBuf> = [".gitignore", ".ignore"]
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|file| file.is_file())
+            .collect();
+        if files.is_empty() {
+            return;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        for file in files {
+            // invalid lines are skipped, the rest of the file still applies
+            builder.add(file);
+        }
+        if let Ok(gitignore) = builder.build() {
+            self.gitignores.push(gitignore);
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = absolute(&self.invocation_dir, path);
+        if self.paths.contains(&path) || self.is_gitignored(&path, is_dir) {
+            return true;
+        }
+        let path = path.to_string_lossy();
+        self.globs.iter().any(|glob| fnmatch(glob, &path))
+    }
+
+    /// The ignore files closest to the path decide, like with git
+    fn is_gitignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut gitignores: Vec<&Gitignore> = self
+            .gitignores
+            .iter()
+            .filter(|g| path.starts_with(g.path()))
+            .collect();
+        gitignores.sort_by_key(|g| std::cmp::Reverse(g.path().components().count()));
+        for gitignore in gitignores {
+            match gitignore.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// `collect_ignore` and `collect_ignore_glob` built from literal lists
+#[derive(Debug, Default, PartialEq)]
+struct CollectIgnore {
+    paths: Vec<String>,
+    globs: Vec<String>,
+}
+
+impl CollectIgnore {
+    fn read(&mut self, body: &[ast::Stmt], target: &Target) {
+        for stmt in body {
+            match &stmt.node {
+                ast::StmtKind::Assign { targets, value, .. } => {
+                    for target in targets {
+                        if let Some(list) = self.list(target) {
+                            *list = strings(value);
+                        }
+                    }
+                }
+                ast::StmtKind::AugAssign {
+                    target,
+                    op: ast::Operator::Add,
+                    value,
+                } => {
+                    if let Some(list) = self.list(target) {
+                        list.extend(strings(value));
+                    }
+                }
+                // `collect_ignore.append("x")` and `collect_ignore.extend([...])`
+                ast::StmtKind::Expr { value } => {
+                    let ast::ExprKind::Call { func, args, .. } = &value.node else {
+                        continue;
+                    };
+                    let ast::ExprKind::Attribute { value, attr, .. } = &func.node else {
+                        continue;
+                    };
+                    let Some(list) = self.list(value) else {
+                        continue;
+                    };
+                    match (attr.as_str(), args.first()) {
+                        ("append", Some(arg)) => list.extend(string(arg)),
+                        ("extend", Some(arg)) => list.extend(strings(arg)),
+                        _ => {}
+                    }
+                }
+                node => {
+                    for body in nested_bodies(node, target) {
+                        self.read(body, target);
+                    }
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, target: &ast::Expr) -> Option<&mut Vec<String>> {
+        match dotted_name(target)?.as_str() {
+            "collect_ignore" => Some(&mut self.paths),
+            "collect_ignore_glob" => Some(&mut self.globs),
+            _ => None,
+        }
+    }
+}
+
+fn strings(expr: &ast::Expr) -> Vec<String> {
+    match &expr.node {
+        ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => {
+            elts.iter().filter_map(string).collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn string(expr: &ast::Expr) -> Option<String> {
+    match &expr.node {
+        ast::ExprKind::Constant {
+            value: ast::Constant::Str(value),
+            ..
+        } => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Path joined to the base dir, `.` and `..` are resolved without touching the filesystem
+fn absolute(base: &Path, path: &Path) -> PathBuf {
+    let mut absolute = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempProject;
+
+    #[test]
+    fn test_collect_ignore() {
+        let source = r#"
+import sys
+
+collect_ignore = ["setup.py"]
+collect_ignore += ["legacy"]
+collect_ignore_glob = ["*_py2.py"]
+if sys.version_info < (3, 10):
+    collect_ignore.append("test_match.py")
+collect_ignore.extend(get_more())
+"#;
+        let program = parse_program(source, "<test>").unwrap();
+        let mut lists = CollectIgnore::default();
+        lists.read(&program, &Target::default());
+        assert_eq!(
+            lists,
+            CollectIgnore {
+                paths: vec!["setup.py", "legacy", "test_match.py"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                globs: vec!["*_py2.py".to_string()],
+            }
+        );
+
+        let target = Target {
+            python_version: Some((3, 11)),
+            platform: None,
+        };
+        let mut lists = CollectIgnore::default();
+        lists.read(&program, &target);
+        assert_eq!(lists.paths, vec!["setup.py", "legacy"]);
+    }
+
+    #[test]
+    fn test_ignore_options() {
+        let config = Config {
+            ignore: vec!["setup.py".to_string()],
+            ignore_glob: vec!["legacy/*".to_string()],
+            ..Config::default()
+        };
+        let ignores = Ignores::new(&config);
+        assert!(ignores.is_ignored(Path::new("setup.py"), false));
+        assert!(ignores.is_ignored(Path::new("legacy/test_old.py"), false));
+        assert!(ignores.is_ignored(Path::new("legacy/unit/test_old.py"), false));
+        assert!(!ignores.is_ignored(Path::new("tests/legacy/test_old.py"), false));
+        assert!(!ignores.is_ignored(Path::new("tests/setup.py"), false));
+    }
+
+    #[test]
+    fn test_files_above_rootdir_are_not_read() {
+        let temp = TempProject::new(&[
+            (".gitignore", "test_*.py\n"),
+            (
+                "conftest.py",
+                "collect_ignore = [\"project/test_api.py\"]\n",
+            ),
+            ("project/.gitignore", "build/\n"),
+            ("project/test_api.py", ""),
+        ]);
+        let config = Config {
+            rootdir: temp.path("project"),
+            ..Config::default()
+        };
+        let mut ignores = Ignores::new(&config);
+        ignores.read_dirs(&temp.path("project"));
+        assert!(!ignores.is_ignored(&temp.path("project/test_api.py"), false));
+        assert!(ignores.is_ignored(&temp.path("project/build"), true));
+
+        // paths outside of the rootdir don't read anything
+        ignores.read_dirs(&temp.root);
+        assert!(!ignores.is_ignored(&temp.path("test_other.py"), false));
+    }
+}
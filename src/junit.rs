@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::entities::{Outcome, TestResult};
+
+/// `<testcase>` of a junit xml report written by pytest
+#[derive(Debug, PartialEq)]
+pub struct TestCase {
+    /// dotted module path plus class names, e.g. `tests.test_api.TestClient`
+    pub classname: String,
+    pub name: String,
+    pub result: TestResult,
+}
+
+impl TestCase {
+    /// Node ids in the report are relative to rootdir, while the classname
+    /// of a collected test is relative to the current directory
+    fn matches(&self, classname: &str) -> bool {
+        self.classname == classname || self.classname.ends_with(&format!(".{classname}"))
+    }
+}
+
+/// Maps the report back to `full_path`s of collected tests
+pub fn results_by_path<'a>(
+    cases: &[TestCase],
+    full_paths: impl Iterator<Item = &'a str>,
+) -> HashMap<String, TestResult> {
+    let mut by_name: HashMap<&str, Vec<&TestCase>> = HashMap::new();
+    for case in cases {
+        by_name.entry(&case.name).or_default().push(case);
+    }
+    let mut results = HashMap::new();
+    for full_path in full_paths {
+        let (classname, name) = junit_names(full_path);
+        let case = by_name
+            .get(name.as_str())
+            .and_then(|cases| cases.iter().find(|c| c.matches(&classname)));
+        if let Some(case) = case {
+            results.insert(full_path.to_string(), case.result.clone());
+        }
+    }
+    results
+}
+
+/// Mirrors how pytest's junitxml plugin mangles node ids into classname and name
+pub fn junit_names(full_path: &str) -> (String, String) {
+    // parameter ids may contain `::` and `/`, they are put back as they are
+    let (path, params) = match full_path.find('[') {
+        Some(bracket) => full_path.split_at(bracket),
+        None => (full_path, ""),
+    };
+    let mut names: Vec<String> = path.split("::").map(String::from).collect();
+    let module = names[0].replace('/', ".");
+    names[0] = module.strip_suffix(".py").unwrap_or(&module).to_string();
+    let name = names.pop().unwrap_or_default() + params;
+    (names.join("."), name)
+}
+
+pub fn parse_report(xml: &str) -> Result<Vec<TestCase>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut cases = vec![];
+    let mut current: Option<TestCase> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"testcase" => current = Some(test_case(&e)?),
+            Event::Empty(e) if e.name().as_ref() == b"testcase" => {
+                cases.push(finish(test_case(&e)?))
+            }
+            Event::Start(e) | Event::Empty(e) => {
+                if let Some(case) = current.as_mut() {
+                    let outcome = element_outcome(&e)?;
+                    case.result.outcome = case.result.outcome.max(outcome);
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"testcase" => {
+                cases.extend(current.take().map(finish))
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(cases)
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    match element.try_get_attribute(name)? {
+        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+fn test_case(element: &BytesStart) -> Result<TestCase> {
+    let seconds = attribute(element, "time")?
+        .and_then(|time| time.parse::<f64>().ok())
+        .unwrap_or_default();
+    Ok(TestCase {
+        classname: attribute(element, "classname")?.unwrap_or_default(),
+        name: attribute(element, "name")?.unwrap_or_default(),
+        result: TestResult {
+            outcome: Outcome::NotRun,
+            duration: Duration::from_secs_f64(seconds.max(0.0)),
+        },
+    })
+}
+
+/// testcase without failure, error or skipped elements has passed
+fn finish(mut case: TestCase) -> TestCase {
+    if case.result.outcome == Outcome::NotRun {
+        case.result.outcome = Outcome::Passed;
+    }
+    case
+}
+
+/// Outcome of a `<failure>`, `<error>` or `<skipped>` element.
+/// Non strict xpasses can't be told apart from passed tests in junit reports
+fn element_outcome(element: &BytesStart) -> Result<Outcome> {
+    let outcome = match element.name().as_ref() {
+        b"failure" => Outcome::Failed,
+        b"error" => Outcome::Error,
+        b"skipped" => {
+            let message = attribute(element, "message")?.unwrap_or_default();
+            match attribute(element, "type")?.as_deref() {
+                _ if message == "xfail-marked test passes unexpectedly" => Outcome::XPass,
+                Some("pytest.xfail") => Outcome::XFail,
+                _ => Outcome::Skipped,
+            }
+        }
+        _ => Outcome::NotRun,
+    };
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_report() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuites><testsuite name="pytest" errors="1" failures="1" skipped="2" tests="6" time="0.5">
+<testcase classname="tests.test_api" name="test_ok" time="0.010" />
+<testcase classname="tests.test_api.TestClient" name="test_get[1-a]" time="0.200">
+  <failure message="assert 1 == 2">def test_get(): ...</failure>
+  <system-out>printed</system-out>
+</testcase>
+<testcase classname="tests.test_api" name="test_skip" time="0.000">
+  <skipped type="pytest.skip" message="not today">tests/test_api.py:10: not today</skipped>
+</testcase>
+<testcase classname="tests.test_api" name="test_xfail" time="0.001">
+  <skipped type="pytest.xfail" message="flaky" />
+</testcase>
+<testcase classname="tests.test_api" name="test_teardown" time="0.001">
+  <error message="failed on teardown with &quot;ValueError&quot;">...</error>
+</testcase>
+</testsuite></testsuites>"#;
+        let cases = parse_report(xml).unwrap();
+        let outcomes: Vec<(&str, Outcome)> = cases
+            .iter()
+            .map(|c| (c.name.as_str(), c.result.outcome))
+            .collect();
+
+        assert_eq!(cases.len(), 5);
+        assert_eq!(cases[1].classname, "tests.test_api.TestClient");
+        assert_eq!(cases[1].result.duration, Duration::from_millis(200));
+        // the last test is collected from a subdirectory of rootdir
+        let full_paths = [
+            "tests/test_api.py::TestClient::test_get[1-a]",
+            "api.py::test_ok",
+            "test_api.py::test_skip",
+        ];
+        let results = results_by_path(&cases, full_paths.into_iter());
+        let mut matched: Vec<&str> = results.keys().map(String::as_str).collect();
+        matched.sort();
+        assert_eq!(
+            matched,
+            vec![
+                "test_api.py::test_skip",
+                "tests/test_api.py::TestClient::test_get[1-a]"
+            ]
+        );
+        assert_eq!(
+            outcomes,
+            vec![
+                ("test_ok", Outcome::Passed),
+                ("test_get[1-a]", Outcome::Failed),
+                ("test_skip", Outcome::Skipped),
+                ("test_xfail", Outcome::XFail),
+                ("test_teardown", Outcome::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_junit_names() {
+        assert_eq!(
+            junit_names("tests/test_api.py::TestClient::test_get[a::b/c]"),
+            (
+                "tests.test_api.TestClient".to_string(),
+                "test_get[a::b/c]".to_string()
+            )
+        );
+        assert_eq!(
+            junit_names("test_api.py::test_ok"),
+            ("test_api".to_string(), "test_ok".to_string())
+        );
+    }
+}
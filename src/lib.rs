@@ -1,5 +1,22 @@
 pub mod app;
+pub mod cache;
+pub mod class_index;
+pub mod config;
+pub mod entities;
+pub mod expression;
 pub mod external_calls;
+pub mod filter;
+pub mod fixtures;
+pub mod formats;
+pub mod ignore;
+pub mod junit;
+pub mod markers;
+pub mod parametrize;
 pub mod parser;
-pub mod entities;
+pub mod runner;
+pub mod target;
+#[cfg(test)]
+mod testing;
+pub mod tree;
 pub mod ui;
+pub mod watcher;
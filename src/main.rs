@@ -5,34 +5,133 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use pytexp::app::{App, InputMode};
+use pytexp::config::Config;
+use pytexp::entities::{CollectionError, ExcludedItem, Parametrization};
 use pytexp::external_calls;
+use pytexp::formats::{self, Format};
 use pytexp::parser;
+use pytexp::target::Target;
 use pytexp::ui::ui;
+use pytexp::watcher::Watcher;
 use std::cmp::min;
+use std::env;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::{error::Error, io};
 use tui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
 };
 
+const TICK_RATE: Duration = Duration::from_millis(50);
+/// file the selected node ids are exported to, use it as `pytest $(cat pytexp-selection.txt)`
+const EXPORT_FILE: &str = "pytexp-selection.txt";
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Directories, files or node ids like `tests/test_api.py::TestApi::test_get` to collect
+    paths: Vec<String>,
     /// Collect test without running ui
-    #[arg(short, long, action)]
+    #[arg(long, visible_alias = "co", action)]
     collect_only: bool,
+    /// Collect tests and list the test-like items pytest leaves out, with the reasons
+    #[arg(long, action)]
+    explain_collection: bool,
+    /// Python version `if sys.version_info ...` blocks are evaluated for, e.g. 3.11
+    #[arg(long, value_parser = parse_python_version)]
+    target_python: Option<(u64, u64)>,
+    /// Platform `if sys.platform ...` blocks are evaluated for, e.g. linux or win32
+    #[arg(long)]
+    target_platform: Option<String>,
+    /// Number of threads test modules are parsed on, all cores by default
+    #[arg(short, long)]
+    jobs: Option<NonZeroUsize>,
+    /// Parse all test modules again instead of reusing the cached ones
+    #[arg(long, action)]
+    cache_clear: bool,
+    /// Don't collect tests again when test files change
+    #[arg(long, action)]
+    no_watch: bool,
+    /// Run changed tests again when their files are saved, `w` toggles it
+    #[arg(long, action, conflicts_with = "no_watch")]
+    rerun_on_save: bool,
+    /// Rootdir to use instead of the one the config file is found in
+    #[arg(long)]
+    rootdir: Option<PathBuf>,
+    /// Config file to use instead of looking for one, its directory is the rootdir
+    #[arg(short = 'c', long = "config")]
+    config_file: Option<PathBuf>,
+    /// Filter the tests list starts with
+    #[arg(long)]
+    filter: Option<String>,
+    /// Print the collected tests in a machine readable format, implies --collect-only
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+}
+
+fn parse_python_version(version: &str) -> Result<(u64, u64), String> {
+    Target::parse_python_version(version)
+        .ok_or_else(|| format!("expected MAJOR.MINOR, got {version}"))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
-    if args.collect_only {
-        let tests = parser::run()?;
-        let tests_count = tests.len();
-        for i in tests {
-            println!("{}", i.test_name);
+    let paths: Vec<PathBuf> = args
+        .paths
+        .iter()
+        .map(|arg| PathBuf::from(arg.split("::").next().unwrap_or_default()))
+        .collect();
+    let mut config = Config::load_for_args(
+        &env::current_dir()?,
+        &paths,
+        args.rootdir.as_deref(),
+        args.config_file.as_deref(),
+    )?;
+    config.target = Target {
+        python_version: args.target_python,
+        platform: args.target_platform.clone(),
+    };
+    config.jobs = args.jobs;
+    let (roots, node_ids) = parser::collect_args(&config, &args.paths)?;
+    if let Some(format) = args.format {
+        let collection = parser::run(&config, &roots, &node_ids, args.cache_clear);
+        formats::write(format, &collection, &mut io::stdout().lock())?;
+        if collection.errors.is_empty() {
+            return Ok(());
+        }
+        if format != Format::Json {
+            for error in &collection.errors {
+                eprintln!("ERROR collecting {error}");
+            }
         }
-        println!("collected {tests_count} tests");
-        return Ok(());
+        std::process::exit(2);
+    }
+    if args.collect_only || args.explain_collection {
+        let collection = parser::run(&config, &roots, &node_ids, args.cache_clear);
+        let tests_count = collection.tests.len();
+        for i in collection.tests {
+            match i.parametrization {
+                Some(Parametrization::Unresolved) => {
+                    println!("{} [{}]", i.test_name, Parametrization::Unresolved)
+                }
+                _ => println!("{}", i.test_name),
+            }
+        }
+        if args.explain_collection {
+            print_excluded_items(&collection.excluded);
+        }
+        if collection.errors.is_empty() {
+            println!("collected {tests_count} tests");
+            return Ok(());
+        }
+        print_collection_errors(&collection.errors);
+        let errors_count = collection.errors.len();
+        let errors = if errors_count == 1 { "error" } else { "errors" };
+        println!("collected {tests_count} tests / {errors_count} {errors}");
+        // pytest exits with "interrupted" when some modules failed to collect
+        std::process::exit(2);
     }
 
     // setup terminal
@@ -45,10 +144,26 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut app = App::new(vec![]);
     app.loading_lock = true;
     terminal.draw(|f| ui(f, &app))?;
-    app.tests = parser::run()?;
-    app.filtered_tests_count = app.tests.len();
+    let collection = parser::run(&config, &roots, &node_ids, args.cache_clear);
+    app.tests = collection.tests;
+    app.collection_errors = collection.errors;
+    app.fixtures = collection.fixtures;
+    app.input = args.filter.unwrap_or_default();
+    app.pytest_options = pytest_options(&args.rootdir, &args.config_file);
+    if !args.paths.is_empty() {
+        // without arguments pytest finds the testpaths of the config itself
+        app.pytest_args = pytest_args(&roots, &node_ids);
+    }
+    app.update_filtered_test_count();
     app.loading_lock = false;
-    let res = run_app(&mut terminal, app);
+    app.auto_rerun = args.rerun_on_save;
+    let watcher = match args.no_watch {
+        true => None,
+        false => Watcher::new(&config, &roots)
+            .map_err(|err| app.set_error(anyhow::anyhow!("watching test files failed: {err}")))
+            .ok(),
+    };
+    let res = run_app(&mut terminal, app, &config, &node_ids, watcher);
 
     // restore terminal
     disable_raw_mode()?;
@@ -66,13 +181,93 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+/// `--rootdir` and `-c` are passed on to pytest, so that it runs with the config tests were
+/// collected with
+fn pytest_options(rootdir: &Option<PathBuf>, config_file: &Option<PathBuf>) -> Vec<String> {
+    let mut options = vec![];
+    if let Some(rootdir) = rootdir {
+        options.push(format!("--rootdir={}", rootdir.display()));
+    }
+    if let Some(config_file) = config_file {
+        options.push("-c".to_string());
+        options.push(config_file.display().to_string());
+    }
+    options
+}
+
+/// Positional arguments which limit pytest to the collected paths, a path some of the
+/// node ids point into is left to them
+fn pytest_args(roots: &[PathBuf], node_ids: &[String]) -> Vec<String> {
+    let mut args: Vec<String> = roots
+        .iter()
+        .filter(|root| {
+            let module = root.strip_prefix(".").unwrap_or(root);
+            !node_ids
+                .iter()
+                .any(|node_id| node_id.split("::").next() == module.to_str())
+        })
+        .map(|root| root.display().to_string())
+        .collect();
+    args.extend(node_ids.iter().cloned());
+    args
+}
+
+/// Writes node ids one per line, so that they can be passed back to pytest
+fn export_node_ids(node_ids: &[String]) -> anyhow::Result<()> {
+    let mut contents = node_ids.join("\n");
+    contents.push('\n');
+    std::fs::write(EXPORT_FILE, contents)?;
+    Ok(())
+}
+
+fn print_excluded_items(excluded: &[ExcludedItem]) {
+    println!("{:=^80}", " EXCLUDED ");
+    for item in excluded {
+        println!("{item}");
+    }
+}
+
+fn print_collection_errors(errors: &[CollectionError]) {
+    println!("{:=^80}", " ERRORS ");
+    for error in errors {
+        println!("{:_^80}", format!(" ERROR collecting {} ", error.path));
+        println!("{error}");
+    }
+}
+
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    config: &Config,
+    node_ids: &[String],
+    mut watcher: Option<Watcher>,
+) -> io::Result<()> {
+    let mut redraw = true;
     loop {
-        terminal.draw(|f| ui(f, &app))?;
+        if redraw {
+            terminal.draw(|f| ui(f, &app))?;
+        }
         let size = terminal.size()?;
         let half_of_height = (size.height / 2) as usize;
 
+        // wake up regularly to show output of the running test
+        if !event::poll(TICK_RATE)? {
+            redraw = app.poll_test_run();
+            if let Some(watcher) = watcher.as_mut() {
+                let changed = watcher.changed_files(config);
+                if !changed.is_empty() {
+                    let (modules, collection) =
+                        parser::recollect(config, &changed, &app.tests, node_ids);
+                    app.update_modules(&modules, collection);
+                    redraw = true;
+                }
+            }
+            redraw |= app.poll_rerun();
+            continue;
+        }
+        redraw = true;
         if let Event::Key(key) = event::read()? {
+            app.notice.clear();
             match app.input_mode {
                 InputMode::TestScrolling => match key.code {
                     KeyCode::Char('2') | KeyCode::Char('l') | KeyCode::Right => {
@@ -90,7 +285,7 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     KeyCode::Down | KeyCode::Char('j') => {
                         app.test_cursor = min(
                             app.test_cursor.saturating_add(1),
-                            app.filtered_tests_count.saturating_sub(1),
+                            app.rows_count.saturating_sub(1),
                         );
                     }
                     KeyCode::PageUp => {
@@ -99,38 +294,65 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     KeyCode::PageDown => {
                         app.test_cursor = min(
                             app.test_cursor.saturating_add(half_of_height),
-                            app.filtered_tests_count.saturating_sub(1),
+                            app.rows_count.saturating_sub(1),
                         );
                     }
                     KeyCode::Home => {
                         app.test_cursor = 0;
                     }
                     KeyCode::End => {
-                        app.test_cursor = app.filtered_tests_count.saturating_sub(1);
+                        app.test_cursor = app.rows_count.saturating_sub(1);
                     }
-                    KeyCode::Enter => {
-                        if let Some(test) = app.find_selected_test() {
-                            app.loading_lock = true;
-                            terminal.draw(|f| ui(f, &app))?;
-                            let output = external_calls::run_test(test.full_path);
-
-                            if !output.stdout.is_empty() {
-                                let temp: String =
-                                    String::from_utf8_lossy(&output.stdout).try_into().unwrap();
-                                app.test_stdout = temp;
-                                app.loading_lock = false;
-                            } else {
-                                let temp: String =
-                                    String::from_utf8_lossy(&output.stderr).try_into().unwrap();
-                                app.test_stdout = temp;
-                                app.loading_lock = false;
+                    KeyCode::Enter => app.run_targets(),
+                    KeyCode::Char(' ') => {
+                        app.toggle_selection();
+                        app.test_cursor = min(
+                            app.test_cursor.saturating_add(1),
+                            app.rows_count.saturating_sub(1),
+                        );
+                    }
+                    KeyCode::Char('v') => app.select_filtered(),
+                    KeyCode::Char('i') => app.invert_selection(),
+                    KeyCode::Char('x') => app.clear_selection(),
+                    KeyCode::Char('y') => {
+                        let node_ids = app.target_node_ids();
+                        match external_calls::copy_to_clipboard(&node_ids.join("\n")) {
+                            Ok(()) => app.notice = format!("copied {} node ids", node_ids.len()),
+                            Err(err) => app.set_error(err),
+                        }
+                    }
+                    KeyCode::Char('e') => {
+                        let node_ids = app.target_node_ids();
+                        match export_node_ids(&node_ids) {
+                            Ok(()) => {
+                                app.notice =
+                                    format!("exported {} node ids to {EXPORT_FILE}", node_ids.len())
                             }
-                        };
+                            Err(err) => app.set_error(err),
+                        }
                     }
+                    KeyCode::Char('a') => app.run_filtered_tests(),
+                    KeyCode::Char('s') => app.toggle_hide_skipped(),
+                    KeyCode::Char('w') if watcher.is_some() => app.toggle_auto_rerun(),
+                    KeyCode::Char('d') => app.show_fixtures(),
+                    KeyCode::Char('E') => app.show_collection_errors(),
+                    KeyCode::Char('t') => app.toggle_tree_mode(),
+                    KeyCode::Char('z') => app.toggle_fold(),
+                    KeyCode::Char('Z') => app.toggle_fold_all(),
+                    KeyCode::Char('F') => app.run_file_under_cursor(),
+                    KeyCode::Char('C') => app.run_class_under_cursor(),
+                    KeyCode::Char('c') => app.cancel_test(),
                     KeyCode::Char('r') => {
-                        if let Some(test) = app.find_selected_test() {
+                        let node_ids = app.target_node_ids();
+                        if !node_ids.is_empty() {
+                            // the `--rootdir` and `-c` of the runs in the ui
+                            let args: Vec<String> = node_ids
+                                .iter()
+                                .chain(&app.pytest_options)
+                                .map(|arg| external_calls::shell_quote(arg))
+                                .collect();
                             let command =
-                                format!("pytest {} -vvv -p no:warnings; exec zsh", test.full_path);
+                                format!("pytest {} -vvv -p no:warnings; exec zsh", args.join(" "));
                             if let Err(err) = external_calls::run_command_in_shell(&command) {
                                 app.set_error(err)
                             }
@@ -139,7 +361,9 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     KeyCode::Char('o') => {
                         // gnome-terminal --title=newTab -- zsh -c "${EDITOR} Cargo.toml"
                         if let Some(test) = app.find_selected_test() {
-                            if let Err(m) = external_calls::open_editor(&test) {
+                            let editor =
+                                external_calls::open_editor(&test.source_path, test.row_location);
+                            if let Err(m) = editor {
                                 app.set_error(m)
                             };
                         }
@@ -148,20 +372,20 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                 },
                 InputMode::FilterEditing => match key.code {
                     KeyCode::Char(c) => {
-                        app.update_filtered_test_count();
                         app.input.push(c);
+                        app.update_filtered_test_count();
                     }
                     KeyCode::Backspace => {
-                        app.update_filtered_test_count();
                         app.input.pop();
+                        app.update_filtered_test_count();
                     }
+                    KeyCode::Tab => app.toggle_filter_mode(),
                     KeyCode::Esc
                     | KeyCode::Enter
                     | KeyCode::Up
                     | KeyCode::Down
                     | KeyCode::End
                     | KeyCode::Home
-                    | KeyCode::Tab
                     | KeyCode::PageDown
                     | KeyCode::PageUp => {
                         app.input_mode = InputMode::TestScrolling;
@@ -176,6 +400,7 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     KeyCode::Char('f') => {
                         app.input_mode = InputMode::FilterEditing;
                     }
+                    KeyCode::Char('c') => app.cancel_test(),
                     KeyCode::Char('q') => {
                         return Ok(());
                     }
@@ -202,6 +427,56 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                     }
                     _ => {}
                 },
+                InputMode::FixtureScrolling => match key.code {
+                    KeyCode::Char('d') | KeyCode::Char('h') | KeyCode::Esc | KeyCode::Left => {
+                        app.input_mode = InputMode::TestScrolling;
+                    }
+                    KeyCode::Char('q') => {
+                        return Ok(());
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app.fixture_cursor = app.fixture_cursor.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app.fixture_cursor = min(
+                            app.fixture_cursor.saturating_add(1),
+                            app.fixture_chain().len().saturating_sub(1),
+                        );
+                    }
+                    KeyCode::Char('o') | KeyCode::Enter => {
+                        if let Some((file, line)) = app.fixture_under_cursor() {
+                            if let Err(err) = external_calls::open_editor(&file, line) {
+                                app.set_error(err)
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                InputMode::CollectionErrorScrolling => match key.code {
+                    KeyCode::Char('E') | KeyCode::Esc | KeyCode::Left | KeyCode::Char('h') => {
+                        app.input_mode = InputMode::TestScrolling;
+                    }
+                    KeyCode::Char('q') => {
+                        return Ok(());
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        app.collection_error_cursor = app.collection_error_cursor.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        app.collection_error_cursor = min(
+                            app.collection_error_cursor.saturating_add(1),
+                            app.collection_errors.len().saturating_sub(1),
+                        );
+                    }
+                    KeyCode::Char('o') | KeyCode::Enter => {
+                        if let Some((file, line)) = app.collection_error_under_cursor() {
+                            if let Err(err) = external_calls::open_editor(&file, line) {
+                                app.set_error(err)
+                            }
+                        }
+                    }
+                    _ => {}
+                },
                 InputMode::ErrorMessage => match key.code {
                     KeyCode::Esc
                     | KeyCode::Enter
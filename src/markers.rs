@@ -0,0 +1,272 @@
+use rustpython_parser::ast;
+
+use crate::entities::ExpectedOutcome;
+use crate::parametrize::{dotted_name, keyword, ModuleScope};
+
+/// Marker name and arguments of a mark expression, e.g. `skipif` for
+/// `@pytest.mark.skipif(cond, reason="...")`. Module level aliases like
+/// `slow = pytest.mark.slow` are followed
+fn mark<'e>(
+    expr: &'e ast::Expr,
+    scope: &ModuleScope<'e>,
+) -> Option<(String, &'e [ast::Expr], &'e [ast::Keyword])> {
+    let expr = scope.resolve(expr);
+    let (func, args, keywords): (&ast::Expr, &[ast::Expr], &[ast::Keyword]) = match &expr.node {
+        ast::ExprKind::Call {
+            func,
+            args,
+            keywords,
+        } => (func, args, keywords),
+        _ => (expr, &[], &[]),
+    };
+    let name = dotted_name(scope.resolve(func))?;
+    let name = name
+        .strip_prefix("pytest.mark.")
+        .or_else(|| name.strip_prefix("mark."))?;
+    (!name.contains('.')).then(|| (name.to_string(), args, keywords))
+}
+
+pub fn names<'e>(
+    marks: impl IntoIterator<Item = &'e ast::Expr>,
+    scope: &ModuleScope,
+) -> Vec<String> {
+    marks
+        .into_iter()
+        .filter_map(|m| mark(m, scope).map(|(name, _, _)| name))
+        .collect()
+}
+
+/// Mark expressions of `pytestmark = pytest.mark.x` or `pytestmark = [pytest.mark.x, ...]`
+/// assigned in the statements, the last assignment wins
+pub fn pytestmark<'e>(body: &'e [ast::Stmt], scope: &ModuleScope<'e>) -> Vec<&'e ast::Expr> {
+    let mut marks = vec![];
+    for stmt in body {
+        if let ast::StmtKind::Assign { targets, value, .. } = &stmt.node {
+            if targets
+                .iter()
+                .any(|t| dotted_name(t).as_deref() == Some("pytestmark"))
+            {
+                marks = match &scope.resolve(value).node {
+                    ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => {
+                        elts.iter().collect()
+                    }
+                    _ => vec![&**value],
+                };
+            }
+        }
+    }
+    marks
+}
+
+/// Fixture names of `pytest.mark.usefixtures` marks
+pub fn usefixtures<'e>(
+    marks: impl IntoIterator<Item = &'e ast::Expr>,
+    scope: &ModuleScope,
+) -> Vec<String> {
+    marks
+        .into_iter()
+        .filter_map(|m| mark(m, scope))
+        .filter(|(name, _, _)| name == "usefixtures")
+        .flat_map(|(_, args, _)| args.iter().filter_map(string_constant))
+        .collect()
+}
+
+/// Appends the markers which aren't in the list yet, keeping the order
+pub fn merge(markers: &mut Vec<String>, other: &[String]) {
+    for marker in other {
+        if !markers.contains(marker) {
+            markers.push(marker.clone());
+        }
+    }
+}
+
+/// skip, skipif and xfail marks among the mark expressions
+pub fn expected_outcomes<'e>(
+    marks: impl IntoIterator<Item = &'e ast::Expr>,
+    scope: &ModuleScope,
+    source: &str,
+) -> Vec<ExpectedOutcome> {
+    marks
+        .into_iter()
+        .filter_map(|m| expected_outcome(m, scope, source))
+        .collect()
+}
+
+fn expected_outcome(
+    expr: &ast::Expr,
+    scope: &ModuleScope,
+    source: &str,
+) -> Option<ExpectedOutcome> {
+    let (name, args, keywords) = mark(expr, scope)?;
+    let reason = keyword(keywords, "reason").and_then(string_constant);
+    match name.as_str() {
+        "skip" => Some(ExpectedOutcome::Skip {
+            reason: reason.or_else(|| args.first().and_then(string_constant)),
+        }),
+        "skipif" => match condition(args, keyword(keywords, "condition"), source) {
+            None | Some(Condition::Always) => Some(ExpectedOutcome::Skip { reason }),
+            Some(Condition::Never) => None,
+            Some(Condition::Source(condition)) => {
+                Some(ExpectedOutcome::SkipIf { condition, reason })
+            }
+        },
+        "xfail" => {
+            let condition = match condition(args, keyword(keywords, "condition"), source) {
+                None | Some(Condition::Always) => None,
+                Some(Condition::Never) => return None,
+                Some(Condition::Source(condition)) => Some(condition),
+            };
+            let strict = matches!(
+                keyword(keywords, "strict").map(|e| &e.node),
+                Some(ast::ExprKind::Constant {
+                    value: ast::Constant::Bool(true),
+                    ..
+                })
+            );
+            Some(ExpectedOutcome::XFail {
+                condition,
+                reason,
+                strict,
+            })
+        }
+        _ => None,
+    }
+}
+
+enum Condition {
+    Always,
+    Never,
+    Source(String),
+}
+
+/// Condition of skipif or xfail, None when there is none. Several conditions
+/// apply when any of them is true, strings are expressions pytest evaluates
+fn condition(args: &[ast::Expr], keyword: Option<&ast::Expr>, source: &str) -> Option<Condition> {
+    let mut conditions = vec![];
+    for arg in args.iter().chain(keyword) {
+        match &arg.node {
+            ast::ExprKind::Constant {
+                value: ast::Constant::Bool(value),
+                ..
+            } => match value {
+                true => return Some(Condition::Always),
+                false => continue,
+            },
+            ast::ExprKind::Constant {
+                value: ast::Constant::Str(condition),
+                ..
+            } => conditions.push(condition.clone()),
+            _ => conditions.push(source_segment(source, arg).unwrap_or_else(|| "?".to_string())),
+        }
+    }
+    match conditions.is_empty() {
+        true if args.is_empty() && keyword.is_none() => None,
+        true => Some(Condition::Never),
+        false => Some(Condition::Source(conditions.join(" or "))),
+    }
+}
+
+/// Module level `pytest.skip(..., allow_module_level=True)` and `pytest.importorskip(...)`,
+/// the ones inside of `if` statements are conditional
+pub fn module_level_skips(body: &[ast::Stmt], source: &str) -> Vec<ExpectedOutcome> {
+    let mut outcomes = vec![];
+    for stmt in body {
+        match &stmt.node {
+            ast::StmtKind::Expr { value } | ast::StmtKind::Assign { value, .. } => {
+                outcomes.extend(module_level_skip(value, source))
+            }
+            ast::StmtKind::If { test, body, .. } => {
+                let condition = source_segment(source, test).unwrap_or_else(|| "?".to_string());
+                for outcome in module_level_skips(body, source) {
+                    outcomes.push(match outcome {
+                        ExpectedOutcome::Skip { reason } => ExpectedOutcome::SkipIf {
+                            condition: condition.clone(),
+                            reason,
+                        },
+                        ExpectedOutcome::SkipIf {
+                            condition: inner,
+                            reason,
+                        } => ExpectedOutcome::SkipIf {
+                            condition: format!("{condition} and {inner}"),
+                            reason,
+                        },
+                        outcome => outcome,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    outcomes
+}
+
+fn module_level_skip(expr: &ast::Expr, source: &str) -> Option<ExpectedOutcome> {
+    let ast::ExprKind::Call {
+        func,
+        args,
+        keywords,
+    } = &expr.node
+    else {
+        return None;
+    };
+    match dotted_name(func).as_deref() {
+        Some("pytest.skip") => {
+            let allowed = matches!(
+                keyword(keywords, "allow_module_level").map(|e| &e.node),
+                Some(ast::ExprKind::Constant {
+                    value: ast::Constant::Bool(true),
+                    ..
+                })
+            );
+            let reason = args
+                .first()
+                .or_else(|| keyword(keywords, "reason"))
+                .or_else(|| keyword(keywords, "msg"))
+                .and_then(string_constant);
+            allowed.then_some(ExpectedOutcome::Skip { reason })
+        }
+        Some("pytest.importorskip") => Some(ExpectedOutcome::SkipIf {
+            condition: source_segment(source, expr).unwrap_or_else(|| "importorskip".to_string()),
+            reason: keyword(keywords, "reason").and_then(string_constant),
+        }),
+        _ => None,
+    }
+}
+
+fn string_constant(expr: &ast::Expr) -> Option<String> {
+    match &expr.node {
+        ast::ExprKind::Constant {
+            value: ast::Constant::Str(value),
+            ..
+        } => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Source text of the expression, lines are joined with spaces
+pub fn source_segment(source: &str, expr: &ast::Expr) -> Option<String> {
+    let (start, end) = (expr.location, expr.end_location?);
+    let lines: Vec<&str> = source
+        .lines()
+        .skip(start.row().checked_sub(1)?)
+        .take(end.row() + 1 - start.row())
+        .collect();
+    let mut segment = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let from = if i == 0 { start.column() } else { 0 };
+        let to = if i + 1 == lines.len() {
+            end.column()
+        } else {
+            chars.len()
+        };
+        segment.push(
+            chars
+                .get(from..to.min(chars.len()))?
+                .iter()
+                .collect::<String>(),
+        );
+    }
+    let segment: Vec<&str> = segment.iter().map(|s| s.trim()).collect();
+    Some(segment.join(" "))
+}
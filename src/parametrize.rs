@@ -0,0 +1,472 @@
+use std::collections::{HashMap, HashSet};
+
+use rustpython_parser::ast;
+use serde::{Deserialize, Serialize};
+
+/// Ids generated by a single `pytest.mark.parametrize` decorator
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ParamIds {
+    Resolved(Vec<String>),
+    Unresolved,
+}
+
+/// Module level names which parametrize arguments are allowed to refer to
+#[derive(Default)]
+pub struct ModuleScope<'a> {
+    assignments: HashMap<&'a str, &'a ast::Expr>,
+    definitions: HashSet<&'a str>,
+}
+
+impl<'a> ModuleScope<'a> {
+    pub fn from_body(body: &'a [ast::Stmt]) -> Self {
+        let mut scope = ModuleScope::default();
+        for stmt in body {
+            match &stmt.node {
+                ast::StmtKind::FunctionDef { name, .. }
+                | ast::StmtKind::AsyncFunctionDef { name, .. }
+                | ast::StmtKind::ClassDef { name, .. } => {
+                    scope.assignments.remove(name.as_str());
+                    scope.definitions.insert(name);
+                }
+                ast::StmtKind::Assign { targets, value, .. } => {
+                    for target in targets {
+                        if let ast::ExprKind::Name { id, .. } = &target.node {
+                            scope.definitions.remove(id.as_str());
+                            scope.assignments.insert(id, value);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        scope
+    }
+
+    /// follows names bound at module level down to the expression they were assigned
+    pub fn resolve<'e>(&self, mut expr: &'e ast::Expr) -> &'e ast::Expr
+    where
+        'a: 'e,
+    {
+        // the limit protects against `a = b; b = a`
+        for _ in 0..16 {
+            match &expr.node {
+                ast::ExprKind::Name { id, .. } => match self.assignments.get(id.as_str()) {
+                    Some(value) => expr = *value,
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        expr
+    }
+}
+
+pub fn dotted_name(expr: &ast::Expr) -> Option<String> {
+    match &expr.node {
+        ast::ExprKind::Name { id, .. } => Some(id.clone()),
+        ast::ExprKind::Attribute { value, attr, .. } => {
+            dotted_name(value).map(|prefix| format!("{prefix}.{attr}"))
+        }
+        _ => None,
+    }
+}
+
+pub fn keyword<'e>(keywords: &'e [ast::Keyword], name: &str) -> Option<&'e ast::Expr> {
+    keywords
+        .iter()
+        .find(|k| k.node.arg.as_deref() == Some(name))
+        .map(|k| &k.node.value)
+}
+
+/// Returns ids for the decorator if it is `pytest.mark.parametrize(...)`
+pub fn from_decorator(decorator: &ast::Expr, scope: &ModuleScope) -> Option<ParamIds> {
+    let ast::ExprKind::Call {
+        func,
+        args,
+        keywords,
+    } = &decorator.node
+    else {
+        return None;
+    };
+    match dotted_name(func).as_deref() {
+        Some("pytest.mark.parametrize") | Some("mark.parametrize") => {
+            Some(match generate_ids(args, keywords, scope) {
+                Some(ids) => ParamIds::Resolved(make_unique(ids)),
+                None => ParamIds::Unresolved,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Argument names of a `pytest.mark.parametrize` decorator, they aren't fixtures
+pub fn argnames(decorator: &ast::Expr) -> Vec<String> {
+    let ast::ExprKind::Call {
+        func,
+        args,
+        keywords,
+    } = &decorator.node
+    else {
+        return vec![];
+    };
+    match dotted_name(func).as_deref() {
+        Some("pytest.mark.parametrize") | Some("mark.parametrize") => args
+            .first()
+            .or_else(|| keyword(keywords, "argnames"))
+            .and_then(parse_argnames)
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+/// Combines ids of stacked decorators, ordered from the closest to the function,
+/// into final ids the same way pytest does. None if any of them is unresolved.
+pub fn expand(marks: &[ParamIds]) -> Option<Vec<String>> {
+    let mut result: Vec<String> = vec![];
+    for mark in marks {
+        let ParamIds::Resolved(ids) = mark else {
+            return None;
+        };
+        result = if result.is_empty() {
+            ids.clone()
+        } else {
+            result
+                .iter()
+                .flat_map(|prev| ids.iter().map(move |id| format!("{prev}-{id}")))
+                .collect()
+        };
+    }
+    Some(result)
+}
+
+fn generate_ids(
+    args: &[ast::Expr],
+    keywords: &[ast::Keyword],
+    scope: &ModuleScope,
+) -> Option<Vec<String>> {
+    let argnames = args.first().or_else(|| keyword(keywords, "argnames"))?;
+    let argnames = parse_argnames(scope.resolve(argnames))?;
+    let argvalues = args.get(1).or_else(|| keyword(keywords, "argvalues"))?;
+    let argvalues = match &scope.resolve(argvalues).node {
+        ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => elts,
+        _ => return None,
+    };
+    let explicit_ids = match args.get(3).or_else(|| keyword(keywords, "ids")) {
+        Some(ids) => parse_explicit_ids(scope.resolve(ids), scope)?,
+        None => vec![],
+    };
+
+    if argvalues.is_empty() {
+        // pytest still generates a single (skipped) test for an empty parameter set
+        let id = argnames
+            .iter()
+            .map(|name| format!("{name}0"))
+            .collect::<Vec<_>>()
+            .join("-");
+        return Some(vec![id]);
+    }
+
+    let mut ids = vec![];
+    for (idx, value) in argvalues.iter().enumerate() {
+        let value = scope.resolve(value);
+        let (values, param_id) = match &value.node {
+            ast::ExprKind::Call {
+                func,
+                args,
+                keywords,
+            } if matches!(
+                dotted_name(func).as_deref(),
+                Some("pytest.param") | Some("param")
+            ) =>
+            {
+                let param_id = match keyword(keywords, "id").map(|id| &id.node) {
+                    None
+                    | Some(ast::ExprKind::Constant {
+                        value: ast::Constant::None,
+                        ..
+                    }) => None,
+                    Some(ast::ExprKind::Constant {
+                        value: ast::Constant::Str(id),
+                        ..
+                    }) => Some(escape_str(id)),
+                    Some(_) => return None,
+                };
+                (args.iter().collect::<Vec<_>>(), param_id)
+            }
+            _ if argnames.len() == 1 => (vec![value], None),
+            ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => {
+                (elts.iter().collect(), None)
+            }
+            _ => return None,
+        };
+        if values.len() != argnames.len() {
+            return None;
+        }
+
+        let id = match param_id.or_else(|| explicit_ids.get(idx).cloned().flatten()) {
+            Some(id) => id,
+            None => {
+                let mut parts = vec![];
+                for (value, argname) in values.into_iter().zip(&argnames) {
+                    parts.push(match value_id(value, scope, 0) {
+                        ValueId::Text(text) => text,
+                        ValueId::Fallback => format!("{argname}{idx}"),
+                        ValueId::Unknown => return None,
+                    });
+                }
+                parts.join("-")
+            }
+        };
+        ids.push(id);
+    }
+    Some(ids)
+}
+
+fn parse_argnames(expr: &ast::Expr) -> Option<Vec<String>> {
+    let names: Vec<String> = match &expr.node {
+        ast::ExprKind::Constant {
+            value: ast::Constant::Str(names),
+            ..
+        } => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+        ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => elts
+            .iter()
+            .map(|e| match &e.node {
+                ast::ExprKind::Constant {
+                    value: ast::Constant::Str(name),
+                    ..
+                } => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    (!names.is_empty()).then_some(names)
+}
+
+fn parse_explicit_ids(expr: &ast::Expr, scope: &ModuleScope) -> Option<Vec<Option<String>>> {
+    match &expr.node {
+        ast::ExprKind::Constant {
+            value: ast::Constant::None,
+            ..
+        } => Some(vec![]),
+        ast::ExprKind::List { elts, .. } | ast::ExprKind::Tuple { elts, .. } => elts
+            .iter()
+            .map(|e| match &e.node {
+                ast::ExprKind::Constant {
+                    value: ast::Constant::None,
+                    ..
+                } => Some(None),
+                _ => match value_id(e, scope, 0) {
+                    ValueId::Text(text) => Some(Some(text)),
+                    _ => None,
+                },
+            })
+            .collect(),
+        // a callable producing ids can't be evaluated statically
+        _ => None,
+    }
+}
+
+enum ValueId {
+    /// pytest renders the value itself
+    Text(String),
+    /// pytest falls back to `{argname}{index}`
+    Fallback,
+    Unknown,
+}
+
+fn value_id(expr: &ast::Expr, scope: &ModuleScope, depth: usize) -> ValueId {
+    match &expr.node {
+        ast::ExprKind::Constant { value, .. } => match value {
+            ast::Constant::Str(s) => ValueId::Text(escape_str(s)),
+            ast::Constant::Bytes(b) => ValueId::Text(escape_bytes(b)),
+            ast::Constant::Int(i) => ValueId::Text(i.to_string()),
+            ast::Constant::Float(f) => ValueId::Text(float_repr(*f)),
+            ast::Constant::Complex { real, imag } => ValueId::Text(complex_repr(*real, *imag)),
+            ast::Constant::Bool(true) => ValueId::Text("True".to_string()),
+            ast::Constant::Bool(false) => ValueId::Text("False".to_string()),
+            ast::Constant::None => ValueId::Text("None".to_string()),
+            // tuples and Ellipsis have no readable representation
+            _ => ValueId::Fallback,
+        },
+        ast::ExprKind::UnaryOp { op, operand }
+            if matches!(op, ast::Unaryop::USub | ast::Unaryop::UAdd) =>
+        {
+            let sign = match op {
+                ast::Unaryop::USub => "-",
+                _ => "",
+            };
+            match &operand.node {
+                ast::ExprKind::Constant {
+                    value: ast::Constant::Int(i),
+                    ..
+                } => ValueId::Text(format!("{sign}{i}")),
+                ast::ExprKind::Constant {
+                    value: ast::Constant::Float(f),
+                    ..
+                } => ValueId::Text(format!("{sign}{}", float_repr(*f))),
+                _ => ValueId::Unknown,
+            }
+        }
+        // functions and classes are rendered by their __name__
+        ast::ExprKind::Name { id, .. } if scope.definitions.contains(id.as_str()) => {
+            ValueId::Text(id.clone())
+        }
+        ast::ExprKind::Name { id, .. } => match scope.assignments.get(id.as_str()) {
+            Some(value) if depth < 16 => value_id(value, scope, depth + 1),
+            _ => ValueId::Unknown,
+        },
+        ast::ExprKind::Lambda { .. } => ValueId::Text("<lambda>".to_string()),
+        ast::ExprKind::List { .. }
+        | ast::ExprKind::Tuple { .. }
+        | ast::ExprKind::Dict { .. }
+        | ast::ExprKind::Set { .. }
+        | ast::ExprKind::ListComp { .. }
+        | ast::ExprKind::SetComp { .. }
+        | ast::ExprKind::DictComp { .. }
+        | ast::ExprKind::GeneratorExp { .. }
+        | ast::ExprKind::Call { .. } => ValueId::Fallback,
+        _ => ValueId::Unknown,
+    }
+}
+
+/// pytest.mark.parametrize requires ids to be unique, duplicates get an index suffix
+fn make_unique(mut ids: Vec<String>) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for id in &ids {
+        *counts.entry(id.clone()).or_default() += 1;
+    }
+    if counts.values().all(|count| *count == 1) {
+        return ids;
+    }
+    let mut taken: HashSet<String> = ids.iter().cloned().collect();
+    let mut suffixes: HashMap<String, usize> = HashMap::new();
+    for id in ids.iter_mut() {
+        if counts[id.as_str()] == 1 {
+            continue;
+        }
+        let separator = match id.chars().last() {
+            Some(c) if c.is_ascii_digit() => "_",
+            _ => "",
+        };
+        let suffix = suffixes.entry(id.clone()).or_default();
+        let mut new_id = format!("{id}{separator}{suffix}");
+        while taken.contains(&new_id) {
+            *suffix += 1;
+            new_id = format!("{id}{separator}{suffix}");
+        }
+        *suffix += 1;
+        taken.insert(new_id.clone());
+        *id = new_id;
+    }
+    ids
+}
+
+/// str.encode("unicode_escape") as pytest applies it to string ids
+fn escape_str(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ' '..='~' => escaped.push(c),
+            c if (c as u32) < 0x100 => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c if (c as u32) < 0x10000 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    escaped
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for b in bytes {
+        match b {
+            b'\t' => escaped.push_str("\\t"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b' '..=b'~' => escaped.push(*b as char),
+            b => escaped.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    escaped
+}
+
+/// python's repr() of a float
+fn float_repr(f: f64) -> String {
+    if f.is_nan() {
+        return "nan".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let scientific = format!("{f:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if (-4..16).contains(&exponent) {
+        let positional = format!("{f}");
+        if positional.contains('.') {
+            positional
+        } else {
+            format!("{positional}.0")
+        }
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{mantissa}e{sign}{:02}", exponent.abs())
+    }
+}
+
+fn complex_repr(real: f64, imag: f64) -> String {
+    let part = |f: f64| {
+        let repr = float_repr(f);
+        repr.strip_suffix(".0").map(String::from).unwrap_or(repr)
+    };
+    if real == 0.0 && real.is_sign_positive() {
+        format!("{}j", part(imag))
+    } else {
+        let sign = if imag.is_sign_negative() { "" } else { "+" };
+        format!("({}{sign}{}j)", part(real), part(imag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_rendering() {
+        assert_eq!(escape_str("a\tb\\ü😀"), "a\\tb\\\\\\xfc\\U0001f600");
+        assert_eq!(escape_bytes(b"ab\x00\xff"), "ab\\x00\\xff");
+        assert_eq!(float_repr(1.0), "1.0");
+        assert_eq!(float_repr(0.1), "0.1");
+        assert_eq!(float_repr(1e16), "1e+16");
+        assert_eq!(float_repr(1.5e-5), "1.5e-05");
+        assert_eq!(complex_repr(0.0, 2.0), "2j");
+        assert_eq!(complex_repr(1.0, -2.5), "(1-2.5j)");
+    }
+
+    #[test]
+    fn test_stacked_ids_and_duplicates() {
+        let marks = vec![
+            ParamIds::Resolved(vec!["2".to_string(), "3".to_string()]),
+            ParamIds::Resolved(vec!["0".to_string(), "1".to_string()]),
+        ];
+        assert_eq!(expand(&marks).unwrap(), vec!["2-0", "2-1", "3-0", "3-1"]);
+        assert_eq!(expand(&[marks[0].clone(), ParamIds::Unresolved]), None);
+
+        let ids = vec![
+            "a".to_string(),
+            "a".to_string(),
+            "1".to_string(),
+            "1".to_string(),
+        ];
+        assert_eq!(make_unique(ids), vec!["a0", "a1", "1_0", "1_1"]);
+    }
+}
@@ -1,123 +1,779 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use rustpython_parser::ast;
+use rustpython_parser::error::ParseError;
 use rustpython_parser::parse_program;
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
-use crate::entities::ParsedTest;
+use crate::cache::{content_hash, ModuleCache};
+use crate::class_index::ClassIndex;
+use crate::config::Config;
+use crate::entities::{
+    ClassAttribute, Collection, CollectionError, ModuleItem, ParsedClass, ParsedFixture,
+    ParsedFunction, ParsedModule, ParsedTest,
+};
+use crate::fixtures::{self, FixtureIndex};
+use crate::ignore::Ignores;
+use crate::markers;
+use crate::parametrize::{self, dotted_name, ModuleScope, ParamIds};
+use crate::target::Target;
 
-impl ParsedTest {
-    fn new(name: String, location: &ast::Location, filepath: &str) -> Self {
-        ParsedTest {
-            test_name: name.clone(),
-            row_location: location.row(),
-            full_path: format!("{filepath}::{name}"),
-        }
+pub fn parse_file(path: PathBuf) -> Result<Vec<ParsedTest>> {
+    let module = parse_module(&path, &Target::default())?;
+    let mut index = ClassIndex::default();
+    index.insert(module);
+    Ok(index.collect_tests(&path, &Config::default()).tests)
+}
+
+pub fn parse_source(contents: &str, filepath: &str) -> Result<Vec<ParsedTest>> {
+    Ok(collect_source(contents, filepath)?.tests)
+}
+
+/// Tests of the module source along with the items pytest wouldn't collect
+pub fn collect_source(contents: &str, filepath: &str) -> Result<Collection> {
+    let module = parse_module_source(contents, filepath, &Target::default())?;
+    let mut index = ClassIndex::default();
+    index.insert(module);
+    Ok(index.collect_tests(Path::new(filepath), &Config::default()))
+}
+
+pub fn parse_module(path: &Path, target: &Target) -> Result<ParsedModule> {
+    let contents = std::fs::read_to_string(path)?;
+    parse_module_source(&contents, utf8_path(path)?, target)
+}
+
+/// Collected paths are strings, a file name which isn't UTF-8 is a collection error
+pub fn utf8_path(path: &Path) -> Result<&str> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => bail!("file name {} isn't valid UTF-8", path.display()),
     }
 }
 
-pub fn parse_file(path: PathBuf) -> Result<Vec<ParsedTest>> {
-    let contents = std::fs::read_to_string(&path)?;
-    let filepath = path.to_str().unwrap();
-    let python_ast = parse_program(&contents, "<embedded>")?;
-    let mut tests = vec![];
-    for i in python_ast {
+/// What parsing of definitions needs to know about the module
+struct Context<'a> {
+    scope: ModuleScope<'a>,
+    source: &'a str,
+    target: &'a Target,
+}
+
+pub fn parse_module_source(
+    contents: &str,
+    filepath: &str,
+    target: &Target,
+) -> Result<ParsedModule> {
+    let python_ast = parse_program(contents, "<embedded>")?;
+    let context = Context {
+        scope: ModuleScope::from_body(&python_ast),
+        source: contents,
+        target,
+    };
+    let scope = &context.scope;
+    let pytestmark = markers::pytestmark(&python_ast, scope);
+    let mut module = ParsedModule {
+        path: filepath.to_string(),
+        markers: markers::names(pytestmark.iter().copied(), scope),
+        expected: markers::expected_outcomes(pytestmark, scope, contents),
+        ..Default::default()
+    };
+    module
+        .expected
+        .extend(markers::module_level_skips(&python_ast, contents));
+    parse_module_body(&mut module, &python_ast, &context);
+    Ok(module)
+}
+
+fn parse_module_body(module: &mut ParsedModule, body: &[ast::Stmt], context: &Context) {
+    for i in body {
         let ast::Located { node, location, .. } = i;
         match node {
-            ast::StmtKind::FunctionDef { name, .. } => {
-                if name.starts_with("test_") {
-                    let test = ParsedTest::new(name, &location, filepath);
-                    tests.push(test);
-                }
+            ast::StmtKind::FunctionDef {
+                name,
+                decorator_list,
+                ..
             }
-            ast::StmtKind::AsyncFunctionDef { name, .. } => {
-                if name.starts_with("test_") {
-                    let test = ParsedTest::new(name, &location, filepath);
-                    tests.push(test);
+            | ast::StmtKind::AsyncFunctionDef {
+                name,
+                decorator_list,
+                ..
+            } => {
+                let function = parse_function(name, decorator_list, i, false, context);
+                if let Some(fixture) = parse_fixture(&function, decorator_list, context) {
+                    define_fixture(&mut module.fixtures, fixture);
                 }
+                module.bind(ModuleItem::Function(function));
             }
             ast::StmtKind::ClassDef {
-                name: class_name,
-                bases: _, //FIXME! add tests from bases
+                name,
+                bases,
                 body,
+                decorator_list,
                 ..
             } => {
-                add_class(class_name, body, &mut tests, filepath, &location);
+                let class = parse_class(name, bases, body, decorator_list, i, context);
+                module.bind(ModuleItem::Class(class));
+            }
+            ast::StmtKind::Assign { targets, value, .. } => {
+                for target in targets {
+                    bind_module_target(module, target, value, location);
+                }
+            }
+            ast::StmtKind::AnnAssign {
+                target,
+                value: Some(value),
+                ..
+            } => bind_module_target(module, target, value, location),
+            ast::StmtKind::Import { names } => {
+                for alias in names {
+                    let ast::AliasData { name, asname } = &alias.node;
+                    match asname {
+                        Some(asname) => module.imports.insert(asname.clone(), name.clone()),
+                        // `import a.b` binds `a`
+                        None => {
+                            let head = name.split('.').next().unwrap();
+                            module.imports.insert(head.to_string(), head.to_string())
+                        }
+                    };
+                }
+            }
+            ast::StmtKind::ImportFrom {
+                module: from,
+                names,
+                level,
+            } => {
+                let mut prefix = ".".repeat(level.unwrap_or(0));
+                if let Some(from) = from {
+                    prefix.push_str(from);
+                }
+                for alias in names {
+                    let ast::AliasData { name, asname } = &alias.node;
+                    if name == "*" {
+                        module.star_imports.push(prefix.clone());
+                        continue;
+                    }
+                    let target = match prefix.ends_with('.') || prefix.is_empty() {
+                        true => format!("{prefix}{name}"),
+                        false => format!("{prefix}.{name}"),
+                    };
+                    module
+                        .imports
+                        .insert(asname.as_ref().unwrap_or(name).clone(), target);
+                }
+            }
+            node => {
+                for body in nested_bodies(node, context.target) {
+                    parse_module_body(module, body, context);
+                }
             }
-            _ => {}
         }
     }
-    Ok(tests)
 }
 
-fn add_class(
-    class_name: String,
-    body: Vec<ast::Located<ast::StmtKind>>,
-    input: &mut Vec<ParsedTest>,
-    filepath: &str,
-    class_location: &ast::Location,
+/// Bodies of `if`, `try` and `with` statements which run when the module or class
+/// is imported. Conditions are evaluated against the target, both branches of
+/// an `if` are walked when it's unknown which one runs
+pub fn nested_bodies<'s>(node: &'s ast::StmtKind, target: &Target) -> Vec<&'s [ast::Stmt]> {
+    match node {
+        ast::StmtKind::If { test, body, orelse } => match target.evaluate(test) {
+            Some(true) => vec![body],
+            Some(false) => vec![orelse],
+            None => vec![body, orelse],
+        },
+        ast::StmtKind::Try {
+            body,
+            orelse,
+            finalbody,
+            ..
+        } => vec![body, orelse, finalbody],
+        ast::StmtKind::With { body, .. } | ast::StmtKind::AsyncWith { body, .. } => vec![body],
+        _ => vec![],
+    }
+}
+
+/// `test_x = ...` shadows a definition of `test_x`, `test_x.__test__ = False`
+/// turns its collection off
+fn bind_module_target(
+    module: &mut ParsedModule,
+    target: &ast::Expr,
+    value: &ast::Expr,
+    location: &ast::Location,
 ) {
-    if class_name.starts_with("Test") {
-        let mut tests_in_class = vec![];
-        for m in body {
-            let ast::Located {
-                node: m_node,
-                location,
-                ..
-            } = m;
-            match m_node {
-                ast::StmtKind::FunctionDef { name, .. } => {
-                    if name.starts_with("test_") {
-                        let test = ParsedTest::new(format!("{class_name}::{name}"), &location, filepath);
-                        tests_in_class.push(test);
+    match &target.node {
+        ast::ExprKind::Name { id, .. } => module.bind(ModuleItem::Assignment {
+            name: id.clone(),
+            row_location: location.row(),
+        }),
+        ast::ExprKind::Attribute {
+            value: owner, attr, ..
+        } if attr == "__test__" => {
+            let Some(owner) = dotted_name(owner) else {
+                return;
+            };
+            for item in module.items.iter_mut() {
+                match item {
+                    ModuleItem::Function(f) if f.name == owner => {
+                        f.dunder_test = bool_constant(value)
                     }
+                    ModuleItem::Class(c) if c.name == owner => c.dunder_test = bool_constant(value),
+                    _ => {}
                 }
-                ast::StmtKind::AsyncFunctionDef { name, .. } => {
-                    if name.starts_with("test_") {
-                        let test = ParsedTest::new(format!("{class_name}::{name}"), &location, filepath);
-                        tests_in_class.push(test);
-                    }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn bool_constant(expr: &ast::Expr) -> Option<bool> {
+    match &expr.node {
+        ast::ExprKind::Constant {
+            value: ast::Constant::Bool(value),
+            ..
+        } => Some(*value),
+        _ => None,
+    }
+}
+
+fn parse_function(
+    name: &str,
+    decorators: &[ast::Expr],
+    definition: &ast::Stmt,
+    method: bool,
+    context: &Context,
+) -> ParsedFunction {
+    let Context { scope, source, .. } = context;
+    let fixtures = match &definition.node {
+        ast::StmtKind::FunctionDef { args, .. } | ast::StmtKind::AsyncFunctionDef { args, .. } => {
+            let method = method
+                && !decorators
+                    .iter()
+                    .any(|d| dotted_name(d).as_deref() == Some("staticmethod"));
+            requested_fixtures(args, decorators, method, scope)
+        }
+        _ => vec![],
+    };
+    // decorators belong to the definition, e.g. a changed parametrize
+    let first_row = decorators
+        .iter()
+        .map(|d| d.location.row())
+        .chain([definition.location.row()])
+        .min()
+        .unwrap_or_default();
+    let last_row = definition.end_location.map_or(usize::MAX, |l| l.row());
+    let definition_source: Vec<&str> = source
+        .lines()
+        .skip(first_row.saturating_sub(1))
+        .take(last_row.saturating_sub(first_row) + 1)
+        .collect();
+    ParsedFunction {
+        name: name.to_string(),
+        row_location: definition.location.row(),
+        end_row_location: end_row(definition),
+        source_hash: content_hash(&definition_source.join("\n")),
+        parametrize: parametrize_marks(decorators, scope),
+        markers: markers::names(decorators, scope),
+        expected: markers::expected_outcomes(decorators, scope, source),
+        dunder_test: None,
+        fixtures,
+    }
+}
+
+/// Last row of the definition, its first one when the parser doesn't tell
+fn end_row(definition: &ast::Stmt) -> usize {
+    definition
+        .end_location
+        .map_or(definition.location.row(), |l| l.row())
+}
+
+/// Fixtures the function requests: `pytest.mark.usefixtures` names and the
+/// arguments without defaults which aren't parametrized, `self` of methods aside
+fn requested_fixtures(
+    arguments: &ast::Arguments,
+    decorators: &[ast::Expr],
+    method: bool,
+    scope: &ModuleScope,
+) -> Vec<String> {
+    let parametrized: Vec<String> = decorators.iter().flat_map(parametrize::argnames).collect();
+    let positional: Vec<&ast::Arg> = arguments
+        .posonlyargs
+        .iter()
+        .chain(&arguments.args)
+        .collect();
+    let without_default = positional.len().saturating_sub(arguments.defaults.len());
+    // defaults of keyword only arguments can't be told apart, they all count as defaults
+    let keyword_only: &[ast::Arg] = match arguments.kw_defaults.is_empty() {
+        true => &arguments.kwonlyargs,
+        false => &[],
+    };
+    let mut fixtures = markers::usefixtures(decorators, scope);
+    let arguments = positional[..without_default]
+        .iter()
+        .copied()
+        .skip(usize::from(method))
+        .chain(keyword_only);
+    for argument in arguments {
+        let name = &argument.node.arg;
+        if !parametrized.contains(name) && !fixtures.contains(name) {
+            fixtures.push(name.clone());
+        }
+    }
+    fixtures
+}
+
+fn parse_fixture(
+    function: &ParsedFunction,
+    decorators: &[ast::Expr],
+    context: &Context,
+) -> Option<ParsedFixture> {
+    fixtures::parse(
+        &function.name,
+        decorators,
+        &function.fixtures,
+        function.row_location,
+        &context.scope,
+        context.source,
+    )
+}
+
+/// A later definition with the same name replaces the fixture
+fn define_fixture(fixtures: &mut Vec<ParsedFixture>, fixture: ParsedFixture) {
+    fixtures.retain(|f| f.name != fixture.name);
+    fixtures.push(fixture);
+}
+
+fn parse_class(
+    class_name: &str,
+    bases: &[ast::Expr],
+    body: &[ast::Stmt],
+    decorators: &[ast::Expr],
+    definition: &ast::Stmt,
+    context: &Context,
+) -> ParsedClass {
+    let Context { scope, source, .. } = context;
+    let mut class = ParsedClass {
+        name: class_name.to_string(),
+        row_location: definition.location.row(),
+        end_row_location: end_row(definition),
+        bases: bases.iter().filter_map(dotted_name).collect(),
+        parametrize: parametrize_marks(decorators, scope),
+        markers: markers::names(decorators, scope),
+        expected: markers::expected_outcomes(decorators, scope, source),
+        methods: vec![],
+        classes: vec![],
+        attributes: vec![],
+        dunder_test: None,
+        fixtures: vec![],
+    };
+    parse_class_body(&mut class, body, context);
+    let pytestmark = markers::pytestmark(body, scope);
+    markers::merge(
+        &mut class.markers,
+        &markers::names(pytestmark.iter().copied(), scope),
+    );
+    class
+        .expected
+        .extend(markers::expected_outcomes(pytestmark, scope, source));
+    class
+}
+
+fn parse_class_body(class: &mut ParsedClass, body: &[ast::Stmt], context: &Context) {
+    for m in body {
+        match &m.node {
+            ast::StmtKind::FunctionDef {
+                name,
+                decorator_list,
+                ..
+            }
+            | ast::StmtKind::AsyncFunctionDef {
+                name,
+                decorator_list,
+                ..
+            } => {
+                let method = parse_function(name, decorator_list, m, true, context);
+                if let Some(fixture) = parse_fixture(&method, decorator_list, context) {
+                    define_fixture(&mut class.fixtures, fixture);
+                }
+                match class.methods.iter().position(|m| m.name == method.name) {
+                    Some(position) => class.methods[position] = method,
+                    None => class.methods.push(method),
+                }
+            }
+            ast::StmtKind::ClassDef {
+                name,
+                bases,
+                body,
+                decorator_list,
+                ..
+            } => {
+                let nested = parse_class(name, bases, body, decorator_list, m, context);
+                match class.classes.iter().position(|c| c.name == nested.name) {
+                    Some(position) => class.classes[position] = nested,
+                    None => class.classes.push(nested),
                 }
-                _ => (),
+                class.attributes.push(ClassAttribute {
+                    name: name.clone(),
+                    row_location: m.location.row(),
+                });
+            }
+            ast::StmtKind::Assign { targets, value, .. } => {
+                let names: Vec<String> = targets.iter().filter_map(dotted_name).collect();
+                // an attribute assigned after a method with the same name replaces it
+                class.methods.retain(|m| !names.contains(&m.name));
+                if names.iter().any(|n| n == "__test__") {
+                    class.dunder_test = bool_constant(value);
+                }
+                class
+                    .attributes
+                    .extend(names.into_iter().map(|name| ClassAttribute {
+                        name,
+                        row_location: m.location.row(),
+                    }));
+            }
+            ast::StmtKind::AnnAssign {
+                target,
+                value: Some(_),
+                ..
+            } => class
+                .attributes
+                .extend(dotted_name(target).map(|name| ClassAttribute {
+                    name,
+                    row_location: m.location.row(),
+                })),
+            node => {
+                for body in nested_bodies(node, context.target) {
+                    parse_class_body(class, body, context);
+                }
+            }
+        }
+    }
+}
+
+/// parametrize marks in the order pytest applies them: the closest to the definition first
+fn parametrize_marks(decorators: &[ast::Expr], scope: &ModuleScope) -> Vec<ParamIds> {
+    decorators
+        .iter()
+        .rev()
+        .filter_map(|d| parametrize::from_decorator(d, scope))
+        .collect()
+}
+
+/// Paths to collect and node ids which narrow their tests down, from positional
+/// arguments like pytest's: directories, files or `file.py::Class::test` node ids.
+/// Without arguments the paths come from the config
+pub fn collect_args(config: &Config, args: &[String]) -> Result<(Vec<PathBuf>, Vec<String>)> {
+    if args.is_empty() {
+        return Ok((config.collect_paths(&std::env::current_dir()?), vec![]));
+    }
+    let mut roots = vec![];
+    let mut node_ids = vec![];
+    for arg in args {
+        let (root, names) = match arg.split_once("::") {
+            Some((path, names)) => (PathBuf::from(path), Some(names)),
+            None => (PathBuf::from(arg), None),
+        };
+        if !root.exists() {
+            bail!("file or directory not found: {arg}");
+        }
+        if let Some(names) = names {
+            // collected paths don't start with `./`
+            let module = root.strip_prefix(".").unwrap_or(&root);
+            node_ids.push(format!("{}::{names}", module.display()));
+        }
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+    Ok((roots, node_ids))
+}
+
+/// Collects the tests of the roots narrowed down to the node ids, unchanged modules
+/// are taken from the cache of the previous run unless `clear_cache` is set
+pub fn run(
+    config: &Config,
+    roots: &[PathBuf],
+    node_ids: &[String],
+    clear_cache: bool,
+) -> Collection {
+    let cache = ModuleCache::load(config, clear_cache);
+    let mut collection = collect_cached(config, roots, &cache);
+    select_node_ids(&mut collection.tests, node_ids);
+    // without a writable rootdir every run parses all the modules, like before
+    let _ = cache.save();
+    collection
+}
+
+/// Keeps the tests the node ids select, tests of modules without node ids are all kept.
+/// A node id selects the tests under it and its parametrized tests, class rows stay
+/// when a test under them is selected
+pub fn select_node_ids(tests: &mut Vec<ParsedTest>, node_ids: &[String]) {
+    if node_ids.is_empty() {
+        return;
+    }
+    let module = |full_path: &str| full_path.split("::").next().unwrap_or_default().to_string();
+    let selects = |path: &str| {
+        let mut ids = node_ids
+            .iter()
+            .filter(|id| module(id) == module(path))
+            .peekable();
+        ids.peek().is_none()
+            || ids.any(|id| {
+                path.strip_prefix(id.as_str()).map_or(false, |rest| {
+                    rest.is_empty() || rest.starts_with("::") || rest.starts_with('[')
+                })
+            })
+    };
+    let keep: Vec<bool> = tests
+        .iter()
+        .map(|test| {
+            let prefix = format!("{}::", test.full_path);
+            let selects_inner = || {
+                tests
+                    .iter()
+                    .any(|t| t.full_path.starts_with(&prefix) && selects(&t.full_path))
+            };
+            selects(&test.full_path) || (test.kind.is_class() && selects_inner())
+        })
+        .collect();
+    let mut keep = keep.into_iter();
+    tests.retain(|_| keep.next().unwrap_or_default());
+}
+
+/// Collects tests under the given roots. Modules which can't be parsed
+/// are reported as errors and don't stop the collection of the others.
+/// Modules are parsed on `config.jobs` threads and collected in path order
+pub fn collect(config: &Config, roots: &[PathBuf]) -> Collection {
+    collect_cached(config, roots, &ModuleCache::disabled())
+}
+
+pub fn collect_cached(config: &Config, roots: &[PathBuf], cache: &ModuleCache) -> Collection {
+    let mut paths = test_files(config, roots);
+    paths.sort();
+    // overlapping roots, e.g. of a `**` testpath, list files more than once
+    paths.dedup();
+    let modules = parse_modules(&paths, config, cache);
+    let mut index = ClassIndex::new(config.target.clone());
+    let mut fixtures = FixtureIndex::default();
+    let mut test_modules = vec![];
+    let mut errors = vec![];
+    for (path, module) in paths.into_iter().zip(modules) {
+        match module {
+            Ok(module) => {
+                fixtures.insert(&module);
+                index.insert(module);
+                test_modules.push(path);
             }
+            Err(err) => errors.push(collection_error(&path, &err)),
         }
-        if !tests_in_class.is_empty() {
-            let class = ParsedTest::new(class_name, class_location, filepath);
-            input.push(class);
+    }
+    for conftest in fixtures::conftest_paths(test_modules.iter().map(PathBuf::as_path)) {
+        match cache.parse_module(Path::new(&conftest), config) {
+            Ok(module) => fixtures.insert(&module),
+            Err(err) => errors.push(collection_error(Path::new(&conftest), &err)),
+        }
+    }
+    let mut collection = Collection {
+        errors,
+        fixtures,
+        ..Default::default()
+    };
+    for path in test_modules {
+        let module = index.collect_tests(&path, config);
+        collection.tests.extend(module.tests);
+        collection.excluded.extend(module.excluded);
+    }
+    collection
+}
 
-            input.extend(tests_in_class);
+/// Test modules under the given roots
+fn test_files(config: &Config, roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    for root in roots {
+        for entry in walk(config, root) {
+            // explicitly given files are collected whatever their names are
+            let is_test_file = match entry.depth() {
+                0 => entry.file_type().is_file(),
+                _ => entry.file_type().is_file() && config.is_test_file(entry.path()),
+            };
+            if is_test_file {
+                let path = entry.into_path();
+                paths.push(path.strip_prefix(".").unwrap_or(&path).to_path_buf());
+            }
         }
     }
+    paths
 }
 
-pub fn run() -> Result<Vec<ParsedTest>> {
-    let mut res = vec![];
-    for entry in WalkDir::new("tests")
+/// Files and directories collection looks into, `norecursedirs`, virtualenvs
+/// and ignored paths are left out
+pub fn walk<'c>(config: &'c Config, root: &Path) -> impl Iterator<Item = DirEntry> + 'c {
+    let mut ignores = Ignores::new(config);
+    WalkDir::new(root)
         .follow_links(false)
         .into_iter()
+        .filter_entry(move |e| {
+            let is_dir = e.file_type().is_dir();
+            if e.depth() == 0 {
+                let dir = match is_dir {
+                    true => e.path(),
+                    false => e.path().parent().unwrap_or(e.path()),
+                };
+                ignores.read_dirs(dir);
+                return true;
+            }
+            if ignores.is_ignored(e.path(), is_dir) {
+                return false;
+            }
+            if !is_dir {
+                return true;
+            }
+            if config.is_norecursedir(e.path()) || is_skipped_dir(e.path()) {
+                return false;
+            }
+            ignores.read_dirs(e.path());
+            true
+        })
         .filter_map(|e| e.ok())
-    {
-        let f_name = entry.file_name().to_string_lossy();
-        if f_name.ends_with(".py") & (f_name.ends_with("_test.py") | f_name.starts_with("test_")) {
-            let path = entry.into_path();
-            let parsed_tests = parse_file(path.clone())?;
-            res.extend(parsed_tests);
+}
+
+/// `__pycache__` and virtualenvs, which pytest skips whatever `norecursedirs` is.
+/// Virtualenvs are told by their `pyvenv.cfg` like pytest does
+pub fn is_skipped_dir(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| name == "__pycache__")
+        || path.join("pyvenv.cfg").is_file()
+}
+
+/// Collects the tests of the changed files again. Changed test modules are collected
+/// themselves, other modules through the test modules which inherit tests from them,
+/// changed conftest.py files only update the fixtures.
+/// Returns the paths of the re-collected test modules, removed ones included
+pub fn recollect(
+    config: &Config,
+    changed: &[PathBuf],
+    tests: &[ParsedTest],
+    node_ids: &[String],
+) -> (Vec<String>, Collection) {
+    let mut modules = vec![];
+    let mut conftests = vec![];
+    let mut ignores = Ignores::new(config);
+    for path in changed {
+        let Some(name) = path.to_str() else {
+            continue;
+        };
+        ignores.read_dirs(path.parent().unwrap_or(Path::new("")));
+        if ignores.is_ignored(path, false) {
+            continue;
+        }
+        if config.is_test_file(path) {
+            modules.push(name.to_string());
+            continue;
+        }
+        if path.file_name().map_or(false, |f| f == "conftest.py") {
+            conftests.push(path);
+            continue;
+        }
+        for test in tests.iter().filter(|t| t.source_path == name) {
+            let module = test.full_path.split("::").next().unwrap_or_default();
+            modules.push(module.to_string());
+        }
+    }
+    modules.sort();
+    modules.dedup();
+    let roots: Vec<PathBuf> = modules
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .collect();
+    let mut collection = collect(config, &roots);
+    select_node_ids(&mut collection.tests, node_ids);
+    for conftest in conftests {
+        match parse_module(conftest, &config.target) {
+            Ok(module) => collection.fixtures.insert(&module),
+            Err(_) => collection.fixtures.remove(&conftest.to_string_lossy()),
         }
     }
-    Ok(res)
+    (modules, collection)
+}
+
+/// Parses the modules on a pool of `config.jobs` threads, the results
+/// are in the order of the paths
+fn parse_modules(
+    paths: &[PathBuf],
+    config: &Config,
+    cache: &ModuleCache,
+) -> Vec<Result<ParsedModule>> {
+    let jobs = config.jobs().min(paths.len());
+    if jobs <= 1 {
+        return paths
+            .iter()
+            .map(|path| cache.parse_module(path, config))
+            .collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut modules: Vec<Option<Result<ParsedModule>>> = paths.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut parsed = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(i) else {
+                            break;
+                        };
+                        parsed.push((i, cache.parse_module(path, config)));
+                    }
+                    parsed
+                })
+            })
+            .collect();
+        for worker in workers {
+            let parsed = worker
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            for (i, module) in parsed {
+                modules[i] = Some(module);
+            }
+        }
+    });
+    modules
+        .into_iter()
+        .map(|module| module.expect("every module is parsed by a worker"))
+        .collect()
+}
+
+fn collection_error(path: &Path, err: &anyhow::Error) -> CollectionError {
+    let path = path.to_string_lossy().to_string();
+    match err.downcast_ref::<ParseError>() {
+        Some(parse_error) => CollectionError {
+            path,
+            line: Some(parse_error.location.row()),
+            column: Some(parse_error.location.column()),
+            message: parse_error.error.to_string(),
+        },
+        None => CollectionError {
+            path,
+            line: None,
+            column: None,
+            message: err.to_string(),
+        },
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::entities::{ItemKind, Parametrization};
     use crate::parser;
+    use crate::testing::TempProject;
     #[test]
     fn test_file_parsing() {
         let python_source = r#"
 def test_one(a: int, b:int):
     return a + b
 
-class GroupedTests:
+class TestGrouped:
     def test_groupped(a: int, b:int):
         return a + b
 
@@ -125,9 +781,9 @@ def test_two(a: int, b:int):
     return a + b
 
         "#;
-        let k = parser::parse_file(python_source)
+        let k: Vec<String> = parser::parse_source(python_source, "test_file.py")
             .unwrap()
-            .iter()
+            .into_iter()
             .map(|o| o.test_name)
             .collect();
 
@@ -135,10 +791,696 @@ def test_two(a: int, b:int):
             k,
             vec![
                 "test_one".to_string(),
-                "GroupedTests".to_string(),
-                "test_groupped".to_string(),
+                "TestGrouped".to_string(),
+                "TestGrouped::test_groupped".to_string(),
                 "test_two".to_string()
             ]
         );
     }
+
+    #[test]
+    fn test_parametrize_expansion() {
+        let python_source = r#"
+import pytest
+
+CASES = [1, 2]
+
+@pytest.mark.parametrize("x", [0, 1])
+@pytest.mark.parametrize("y, z", [(2, "a"), pytest.param(3, "b", id="custom")])
+def test_stacked(x, y, z):
+    pass
+
+@pytest.mark.parametrize("value", CASES, ids=["one", None])
+def test_ids(value):
+    pass
+
+@pytest.mark.parametrize("obj", [object(), None, -1.5])
+def test_objects(obj):
+    pass
+
+@pytest.mark.parametrize("value", get_cases())
+def test_unresolved(value):
+    pass
+
+@pytest.mark.parametrize("x", [1, 2])
+class TestClass:
+    @pytest.mark.parametrize("y", [True])
+    def test_method(self, x, y):
+        pass
+        "#;
+        let tests = parser::parse_source(python_source, "test_file.py").unwrap();
+        let names: Vec<&str> = tests.iter().map(|t| t.test_name.as_str()).collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "test_stacked[2-a-0]",
+                "test_stacked[2-a-1]",
+                "test_stacked[custom-0]",
+                "test_stacked[custom-1]",
+                "test_ids[one]",
+                "test_ids[2]",
+                "test_objects[obj0]",
+                "test_objects[None]",
+                "test_objects[-1.5]",
+                "test_unresolved",
+                "TestClass",
+                "TestClass::test_method[True-1]",
+                "TestClass::test_method[True-2]",
+            ]
+        );
+        assert_eq!(tests[9].parametrization, Some(Parametrization::Unresolved));
+        assert_eq!(tests[0].full_path, "test_file.py::test_stacked[2-a-0]");
+    }
+
+    #[test]
+    fn test_inherited_tests() {
+        let python_source = r#"
+class Mixin:
+    def test_base(self):
+        pass
+
+    def test_overridden(self):
+        pass
+
+class TestChild(Mixin):
+    def test_overridden(self):
+        pass
+
+    def test_own(self):
+        pass
+        "#;
+        let tests = parser::parse_source(python_source, "test_file.py").unwrap();
+        let k: Vec<(&str, usize)> = tests
+            .iter()
+            .map(|t| (t.test_name.as_str(), t.row_location))
+            .collect();
+
+        assert_eq!(
+            k,
+            vec![
+                ("TestChild", 9),
+                ("TestChild::test_base", 3),
+                ("TestChild::test_overridden", 10),
+                ("TestChild::test_own", 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markers() {
+        let python_source = r#"
+import pytest
+from pytest import mark
+
+pytestmark = [pytest.mark.integration, pytest.mark.filterwarnings("ignore")]
+slow = pytest.mark.slow
+
+@slow
+@mark.skipif(True, reason="never")
+def test_function():
+    pass
+
+@pytest.mark.network
+class TestBase:
+    def test_base(self):
+        pass
+
+class TestChild(TestBase):
+    pytestmark = pytest.mark.child
+
+    @pytest.mark.parametrize("x", [1])
+    def test_own(self, x):
+        pass
+        "#;
+        let tests = parser::parse_source(python_source, "test_file.py").unwrap();
+        let markers: Vec<(&str, Vec<&str>)> = tests
+            .iter()
+            .map(|t| {
+                let markers = t.markers.iter().map(String::as_str).collect();
+                (t.test_name.as_str(), markers)
+            })
+            .collect();
+
+        assert_eq!(
+            markers,
+            vec![
+                (
+                    "test_function",
+                    vec!["slow", "skipif", "integration", "filterwarnings"]
+                ),
+                ("TestBase", vec!["network", "integration", "filterwarnings"]),
+                (
+                    "TestBase::test_base",
+                    vec!["network", "integration", "filterwarnings"]
+                ),
+                (
+                    "TestChild",
+                    vec!["child", "network", "integration", "filterwarnings"]
+                ),
+                (
+                    "TestChild::test_base",
+                    vec!["child", "network", "integration", "filterwarnings"]
+                ),
+                (
+                    "TestChild::test_own[1]",
+                    vec![
+                        "parametrize",
+                        "child",
+                        "network",
+                        "integration",
+                        "filterwarnings"
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expected_outcomes() {
+        let python_source = r#"
+import sys
+import pytest
+
+windows_only = pytest.mark.skipif(sys.platform != "win32", reason="windows")
+
+@pytest.mark.skip(reason="broken")
+def test_skipped():
+    pass
+
+@windows_only
+@pytest.mark.xfail(strict=True)
+def test_windows():
+    pass
+
+@pytest.mark.xfail(
+    sys.version_info < (3, 8),
+    reason="old",
+)
+def test_old_python():
+    pass
+
+@pytest.mark.skipif(False, reason="never")
+def test_plain():
+    pass
+        "#;
+        let tests = parser::parse_source(python_source, "test_file.py").unwrap();
+        let expected: Vec<String> = tests
+            .iter()
+            .map(|t| {
+                t.expected
+                    .as_ref()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        assert_eq!(
+            expected,
+            vec![
+                "skip: broken",
+                "skipif sys.platform != \"win32\": windows",
+                "xfail if sys.version_info < (3, 8): old",
+                "",
+            ]
+        );
+        assert!(tests[0].is_skipped());
+        assert!(!tests[1].is_skipped());
+
+        let python_source = r#"
+import pytest
+np = pytest.importorskip("numpy")
+
+@pytest.mark.xfail(strict=True)
+def test_numpy():
+    pass
+        "#;
+        let tests = parser::parse_source(python_source, "test_file.py").unwrap();
+        assert_eq!(
+            tests[0].expected.as_ref().unwrap().to_string(),
+            "skipif pytest.importorskip(\"numpy\")"
+        );
+    }
+
+    #[test]
+    fn test_unittest_classes() {
+        let python_source = r#"
+import unittest
+from unittest import TestCase as Case
+
+class HttpClientCase(unittest.TestCase):
+    def setUp(self):
+        pass
+
+    def test_get(self):
+        pass
+
+    def helper(self):
+        pass
+
+class DerivedCase(HttpClientCase):
+    def testPost(self):
+        pass
+
+class AbstractCase(Case):
+    __test__ = False
+
+    def test_abstract(self):
+        pass
+
+class TestWithInit:
+    def __init__(self):
+        pass
+
+    def test_never(self):
+        pass
+
+class NoseStyle:
+    __test__ = True
+
+    def test_nose(self):
+        pass
+        "#;
+        let k: Vec<String> = parser::parse_source(python_source, "test_file.py")
+            .unwrap()
+            .into_iter()
+            .map(|o| o.test_name)
+            .collect();
+
+        assert_eq!(
+            k,
+            vec![
+                "HttpClientCase",
+                "HttpClientCase::test_get",
+                "DerivedCase",
+                "DerivedCase::test_get",
+                "DerivedCase::testPost",
+                "NoseStyle",
+                "NoseStyle::test_nose",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_excluded_items() {
+        let python_source = r#"
+def test_helper():
+    pass
+
+test_helper.__test__ = False
+
+def test_shadowed():
+    pass
+
+test_shadowed = None
+test_data = [1, 2]
+
+def test_redefined():
+    pass
+
+class TestWithInit:
+    def __init__(self):
+        pass
+
+    def test_never(self):
+        pass
+
+class TestMethods:
+    def test_a(self):
+        pass
+
+    test_a = None
+    test_value = 1
+
+    def test_b(self):
+        pass
+
+def test_redefined():
+    pass
+        "#;
+        let collection = parser::collect_source(python_source, "test_file.py").unwrap();
+        let names: Vec<(&str, usize)> = collection
+            .tests
+            .iter()
+            .map(|t| (t.test_name.as_str(), t.row_location))
+            .collect();
+        let excluded: Vec<String> = collection.excluded.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            names,
+            vec![
+                ("test_redefined", 33),
+                ("TestMethods", 23),
+                ("TestMethods::test_b", 30),
+            ]
+        );
+        assert_eq!(
+            excluded,
+            vec![
+                "test_file.py::test_helper:2: `__test__ = False`",
+                "test_file.py::test_shadowed:10: not a function",
+                "test_file.py::test_data:11: not a function",
+                "test_file.py::TestWithInit:16: has a `__init__` constructor",
+                "test_file.py::TestMethods::test_a:27: not a function",
+                "test_file.py::TestMethods::test_value:28: not a function",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_classes_and_blocks() {
+        let python_source = r#"
+import sys
+
+if sys.version_info >= (3, 8):
+    def test_new_python():
+        pass
+else:
+    def test_old_python():
+        pass
+
+try:
+    import numpy
+except ImportError:
+    def test_in_handler():
+        pass
+else:
+    def test_numpy():
+        pass
+
+class TestOuter:
+    def test_outer(self):
+        pass
+
+    class TestInner:
+        if sys.platform == "win32":
+            def test_windows(self):
+                pass
+
+        def test_inner(self):
+            pass
+
+    class Helper:
+        def test_helper(self):
+            pass
+        "#;
+        let module = parse_module_source(python_source, "test_file.py", &Target::default());
+        let mut index = ClassIndex::default();
+        index.insert(module.unwrap());
+        let collect = |index: &mut ClassIndex| -> Vec<String> {
+            index
+                .collect_tests(Path::new("test_file.py"), &Config::default())
+                .tests
+                .into_iter()
+                .map(|t| t.test_name)
+                .collect()
+        };
+
+        assert_eq!(
+            collect(&mut index),
+            vec![
+                "test_new_python",
+                "test_old_python",
+                "test_numpy",
+                "TestOuter",
+                "TestOuter::test_outer",
+                "TestOuter::TestInner",
+                "TestOuter::TestInner::test_windows",
+                "TestOuter::TestInner::test_inner",
+            ]
+        );
+
+        let target = Target {
+            python_version: Some((3, 11)),
+            platform: Some("linux".to_string()),
+        };
+        let module = parse_module_source(python_source, "test_file.py", &target);
+        let mut index = ClassIndex::new(target);
+        index.insert(module.unwrap());
+        let tests = collect(&mut index);
+        assert!(tests.contains(&"test_new_python".to_string()));
+        assert!(!tests.contains(&"test_old_python".to_string()));
+        assert!(!tests.contains(&"TestOuter::TestInner::test_windows".to_string()));
+    }
+
+    #[test]
+    fn test_source_hash() {
+        let hashes = |source: &str| -> Vec<u64> {
+            parse_source(source, "test_x.py")
+                .unwrap()
+                .iter()
+                .map(|t| t.source_hash)
+                .collect()
+        };
+        let before = hashes(
+            "@pytest.mark.slow\ndef test_a():\n    assert 1\n\n\ndef test_b():\n    assert 2\n",
+        );
+        let body_changed = hashes(
+            "@pytest.mark.slow\ndef test_a():\n    assert 1\n\n\ndef test_b():\n    assert 3\n",
+        );
+        let decorator_changed = hashes(
+            "@pytest.mark.fast\ndef test_a():\n    assert 1\n\n\ndef test_b():\n    assert 2\n",
+        );
+
+        assert_eq!(before[0], body_changed[0]);
+        assert_ne!(before[1], body_changed[1]);
+        assert_ne!(before[0], decorator_changed[0]);
+        assert_eq!(before[1], decorator_changed[1]);
+    }
+
+    #[test]
+    fn test_select_node_ids() {
+        let mut tests: Vec<ParsedTest> = [
+            "tests/test_a.py::test_one",
+            "tests/test_a.py::test_one_more",
+            "tests/test_a.py::test_two[1]",
+            "tests/test_a.py::test_two[2]",
+            "tests/test_a.py::TestA::test_one",
+            "tests/test_a.py::TestB",
+            "tests/test_a.py::TestB::test_one",
+            "tests/test_a.py::TestB::test_two",
+            "tests/test_b.py::test_one",
+        ]
+        .iter()
+        .map(|full_path| {
+            let (path, name) = full_path.split_once("::").unwrap();
+            let mut test = ParsedTest::new(name.to_string(), 1, path);
+            if !name.starts_with("test") {
+                test.kind = ItemKind::Class;
+            }
+            test
+        })
+        .collect();
+        let node_ids = [
+            "tests/test_a.py::test_one".to_string(),
+            "tests/test_a.py::test_two".to_string(),
+            "tests/test_a.py::TestA".to_string(),
+            "tests/test_a.py::TestB::test_two".to_string(),
+        ];
+        parser::select_node_ids(&mut tests, &node_ids);
+        let full_paths: Vec<&str> = tests.iter().map(|t| t.full_path.as_str()).collect();
+        assert_eq!(
+            full_paths,
+            vec![
+                "tests/test_a.py::test_one",
+                "tests/test_a.py::test_two[1]",
+                "tests/test_a.py::test_two[2]",
+                "tests/test_a.py::TestA::test_one",
+                "tests/test_a.py::TestB",
+                "tests/test_a.py::TestB::test_two",
+                "tests/test_b.py::test_one",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unparsable_module_is_reported() {
+        let project = TempProject::new(&[
+            ("test_broken.py", "def test_broken(:\n    pass\n"),
+            ("test_fine.py", "def test_fine():\n    pass\n"),
+        ]);
+        let collection = collect(&Config::default(), std::slice::from_ref(&project.root));
+
+        let names: Vec<&str> = collection
+            .tests
+            .iter()
+            .map(|t| t.test_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["test_fine"]);
+        assert_eq!(collection.errors.len(), 1);
+        let error = &collection.errors[0];
+        assert_eq!(project.relative(&error.path), "test_broken.py");
+        assert_eq!(error.line, Some(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_file_name() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let project = TempProject::new(&[]);
+        let name = std::ffi::OsStr::from_bytes(b"test_\xff.py");
+        std::fs::write(project.root.join(name), "def test_one():\n    pass\n").unwrap();
+        let collection = collect(&Config::default(), std::slice::from_ref(&project.root));
+
+        assert!(collection.tests.is_empty());
+        assert_eq!(collection.errors.len(), 1);
+        assert!(collection.errors[0].message.contains("isn't valid UTF-8"));
+    }
+
+    #[test]
+    fn test_parallel_collection_is_ordered() {
+        let files: Vec<(String, String)> = (0..20)
+            .map(|i| {
+                let path = format!("pkg{}/test_module_{i}.py", i % 3);
+                let source =
+                    format!("def test_{i}():\n    pass\n\n\ndef test_{i}_b():\n    pass\n");
+                (path, source)
+            })
+            .chain([("pkg0/test_broken.py".to_string(), "def (:\n".to_string())])
+            .collect();
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(path, source)| (path.as_str(), source.as_str()))
+            .collect();
+        let project = TempProject::new(&files);
+
+        let collect = |jobs: usize| {
+            let config = Config {
+                jobs: std::num::NonZeroUsize::new(jobs),
+                ..Default::default()
+            };
+            let collection = collect(&config, std::slice::from_ref(&project.root));
+            let tests: Vec<String> = collection
+                .tests
+                .iter()
+                .map(|t| project.relative(&t.full_path))
+                .collect();
+            (tests, collection.errors.len())
+        };
+        let sequential = collect(1);
+        let parallel = collect(8);
+
+        assert_eq!(parallel, sequential);
+        let (tests, errors) = parallel;
+        assert_eq!(tests.len(), 40);
+        assert_eq!(errors, 1);
+        let mut sorted = tests.clone();
+        sorted.sort_by_key(|t| t.split("::").next().unwrap().to_string());
+        assert_eq!(tests, sorted);
+        assert_eq!(
+            tests[..2],
+            [
+                "pkg0/test_module_0.py::test_0",
+                "pkg0/test_module_0.py::test_0_b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixtures_of_conftest_files() {
+        let project = TempProject::new(&[
+            (
+                "conftest.py",
+                "import pytest\n\n\n@pytest.fixture\ndef db():\n    pass\n",
+            ),
+            (
+                "api/conftest.py",
+                "from pytest import fixture\n\n\n@fixture\ndef client(db):\n    pass\n",
+            ),
+            (
+                "api/test_api.py",
+                "def test_get(client, monkeypatch):\n    pass\n",
+            ),
+        ]);
+        let collection = collect(&Config::default(), std::slice::from_ref(&project.root));
+
+        let chain: Vec<(String, usize, Option<String>)> = collection
+            .fixtures
+            .resolve(&collection.tests[0])
+            .iter()
+            .map(|f| {
+                let path = f.definition.map(|d| project.relative(&d.path));
+                (f.name.clone(), f.depth, path)
+            })
+            .collect();
+        assert_eq!(
+            chain,
+            vec![
+                ("client".to_string(), 0, Some("api/conftest.py".to_string())),
+                ("db".to_string(), 1, Some("conftest.py".to_string())),
+                ("monkeypatch".to_string(), 0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignored_paths_are_not_collected() {
+        let project = TempProject::new(&[
+            (
+                "conftest.py",
+                "collect_ignore = [\"legacy\"]\ncollect_ignore_glob = [\"*_py2.py\"]\n",
+            ),
+            ("test_app.py", "def test_app():\n    pass\n"),
+            ("test_app_py2.py", "def test_app():\n    pass\n"),
+            ("legacy/test_old.py", "def test_old():\n    pass\n"),
+            ("api/conftest.py", "collect_ignore = [\"test_wip.py\"]\n"),
+            ("api/test_api.py", "def test_get():\n    pass\n"),
+            ("api/test_wip.py", "def test_wip():\n    pass\n"),
+            ("vendor/test_lib.py", "def test_lib():\n    pass\n"),
+        ]);
+        let config = Config {
+            rootdir: project.root.clone(),
+            ignore: vec![project.path("vendor").to_string_lossy().to_string()],
+            ..Default::default()
+        };
+        let collection = collect(&config, std::slice::from_ref(&project.root));
+
+        let tests: Vec<String> = collection
+            .tests
+            .iter()
+            .map(|t| project.relative(&t.full_path))
+            .collect();
+        assert_eq!(
+            tests,
+            vec!["api/test_api.py::test_get", "test_app.py::test_app"]
+        );
+    }
+
+    #[test]
+    fn test_ignore_files_and_virtualenvs() {
+        let project = TempProject::new(&[
+            (".gitignore", "out/\ntest_generated_*.py\n"),
+            (".ignore", "!test_generated_keep.py\n"),
+            ("test_app.py", "def test_app():\n    pass\n"),
+            ("test_generated_a.py", "def test_a():\n    pass\n"),
+            ("test_generated_keep.py", "def test_keep():\n    pass\n"),
+            ("out/test_copy.py", "def test_app():\n    pass\n"),
+            ("env/pyvenv.cfg", "home = /usr/bin\n"),
+            ("env/lib/test_installed.py", "def test_lib():\n    pass\n"),
+            ("__pycache__/test_app.py", "def test_app():\n    pass\n"),
+            ("api/.gitignore", "test_local.py\n"),
+            ("api/test_local.py", "def test_local():\n    pass\n"),
+            ("api/test_api.py", "def test_get():\n    pass\n"),
+        ]);
+        let config = Config {
+            rootdir: project.root.clone(),
+            ..Default::default()
+        };
+        let collection = collect(&config, std::slice::from_ref(&project.root));
+
+        let tests: Vec<String> = collection
+            .tests
+            .iter()
+            .map(|t| project.relative(&t.full_path))
+            .collect();
+        assert_eq!(
+            tests,
+            vec![
+                "api/test_api.py::test_get",
+                "test_app.py::test_app",
+                "test_generated_keep.py::test_keep",
+            ]
+        );
+    }
 }
@@ -0,0 +1,336 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::external_calls;
+use crate::junit::{self, TestCase};
+
+static RUNS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// how long pytest gets to shut down after SIGINT before it is killed
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub enum RunStatus {
+    Running,
+    Finished(ExitStatus),
+}
+
+/// pytest process running in the background, its output is read by
+/// separate threads so the ui stays responsive
+pub struct TestRun {
+    /// node ids passed to pytest
+    pub node_ids: Vec<String>,
+    /// other command line options, e.g. `-k` expression
+    pub options: Vec<String>,
+    /// taken by drop, which leaves the shutdown of pytest to a thread
+    child: Option<Child>,
+    lines: Receiver<String>,
+    /// junit xml report pytest writes at the end of the session
+    report_path: PathBuf,
+    interrupted_at: Option<Instant>,
+    status: Option<ExitStatus>,
+}
+
+impl TestRun {
+    pub fn start(node_ids: Vec<String>, options: Vec<String>) -> Result<TestRun> {
+        let report_path = std::env::temp_dir().join(format!(
+            "pytexp-{}-{}.xml",
+            std::process::id(),
+            RUNS_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut child = external_calls::spawn_tests(&node_ids, &options, &report_path)?;
+        let (sender, lines) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward_lines(stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_lines(stderr, sender);
+        }
+        Ok(TestRun {
+            node_ids,
+            options,
+            child: Some(child),
+            lines,
+            report_path,
+            interrupted_at: None,
+            status: None,
+        })
+    }
+
+    /// Moves the output received so far into `output` and reports whether pytest is done
+    pub fn poll(&mut self, output: &mut String) -> RunStatus {
+        let mut output_closed = false;
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => {
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    output_closed = true;
+                    break;
+                }
+            }
+        }
+        if self.status.is_none() {
+            self.status = self.child().try_wait().ok().flatten();
+        }
+        if let (None, Some(interrupted_at)) = (self.status, self.interrupted_at) {
+            if interrupted_at.elapsed() > INTERRUPT_TIMEOUT {
+                self.kill();
+            }
+        }
+        match self.status {
+            // processes started by pytest may keep the pipes open, don't wait for them after a kill
+            Some(status) if output_closed || self.interrupted_at.is_some() => {
+                RunStatus::Finished(status)
+            }
+            _ => RunStatus::Running,
+        }
+    }
+
+    /// Interrupts pytest like Ctrl+C does, the second call kills it
+    pub fn cancel(&mut self) {
+        if self.interrupted_at.is_none() && interrupt(self.child()) {
+            self.interrupted_at = Some(Instant::now());
+        } else {
+            self.interrupted_at.get_or_insert_with(Instant::now);
+            self.kill();
+        }
+    }
+
+    /// Short description of what is running
+    pub fn title(&self) -> String {
+        let mut title = match self.node_ids.as_slice() {
+            [] => "pytest".to_string(),
+            [node_id] => node_id.clone(),
+            node_ids => format!("{} tests", node_ids.len()),
+        };
+        for option in &self.options {
+            title.push(' ');
+            title.push_str(option);
+        }
+        title
+    }
+
+    /// Results of the finished run, there is no report when pytest failed to start the session
+    pub fn report(&self) -> Result<Vec<TestCase>> {
+        let xml = fs::read_to_string(&self.report_path)?;
+        junit::parse_report(&xml)
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        self.interrupted_at.is_some()
+    }
+
+    fn kill(&mut self) {
+        self.status = kill(self.child());
+    }
+
+    fn child(&mut self) -> &mut Child {
+        self.child
+            .as_mut()
+            .expect("the child is only taken on drop")
+    }
+}
+
+impl Drop for TestRun {
+    /// A replaced run gets the same chance to shut down as a cancelled one, a thread
+    /// waits for it so that the ui doesn't
+    fn drop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        let report_path = std::mem::take(&mut self.report_path);
+        if self.status.is_some() || matches!(child.try_wait(), Ok(Some(_))) {
+            let _ = fs::remove_file(report_path);
+            return;
+        }
+        let interrupted_at = match self.interrupted_at {
+            None if interrupt(&child) => Some(Instant::now()),
+            interrupted_at => interrupted_at,
+        };
+        thread::spawn(move || {
+            if let Some(interrupted_at) = interrupted_at {
+                while interrupted_at.elapsed() < INTERRUPT_TIMEOUT {
+                    if !matches!(child.try_wait(), Ok(None)) {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            // the processes pytest started are still around when pytest itself exited
+            kill(&mut child);
+            // pytest writes the report when it's interrupted too
+            let _ = fs::remove_file(report_path);
+        });
+    }
+}
+
+/// Kills the process group of pytest and waits for pytest
+fn kill(child: &mut Child) -> Option<ExitStatus> {
+    if !kill_group(child) {
+        let _ = child.kill();
+    }
+    child.wait().ok()
+}
+
+fn forward_lines<R: Read + Send + 'static>(source: R, sender: Sender<String>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(source);
+        let mut line = vec![];
+        // output isn't always valid utf-8, e.g. bytes a test prints
+        while let Ok(1..) = reader.read_until(b'\n', &mut line) {
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if sender
+                .send(String::from_utf8_lossy(&line).into_owned())
+                .is_err()
+            {
+                break;
+            }
+            line.clear();
+        }
+    });
+}
+
+/// Sends SIGINT to the process group of pytest
+#[cfg(unix)]
+fn interrupt(child: &Child) -> bool {
+    signal_group(child, libc::SIGINT)
+}
+
+#[cfg(not(unix))]
+fn interrupt(_: &Child) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn kill_group(child: &Child) -> bool {
+    signal_group(child, libc::SIGKILL)
+}
+
+#[cfg(not(unix))]
+fn kill_group(_: &Child) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn signal_group(child: &Child, signal: libc::c_int) -> bool {
+    // SAFETY: plain syscall, the child leads the group and wasn't reaped yet
+    unsafe { libc::kill(-(child.id() as libc::pid_t), signal) == 0 }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+
+    fn run(command: &str) -> TestRun {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .process_group(0)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (sender, lines) = mpsc::channel();
+        forward_lines(child.stdout.take().unwrap(), sender.clone());
+        forward_lines(child.stderr.take().unwrap(), sender);
+        TestRun {
+            node_ids: vec![command.to_string()],
+            options: vec![],
+            child: Some(child),
+            lines,
+            report_path: PathBuf::from("missing-report.xml"),
+            interrupted_at: None,
+            status: None,
+        }
+    }
+
+    fn wait(test_run: &mut TestRun, output: &mut String) -> ExitStatus {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(10) {
+            if let RunStatus::Finished(status) = test_run.poll(output) {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("process is still running")
+    }
+
+    #[test]
+    fn test_output_is_streamed() {
+        let mut test_run = run("echo first; echo second >&2; exit 3");
+        let mut output = String::new();
+        let status = wait(&mut test_run, &mut output);
+
+        assert_eq!(status.code(), Some(3));
+        let mut lines: Vec<&str> = output.lines().collect();
+        lines.sort();
+        assert_eq!(lines, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_invalid_utf8_output() {
+        let mut test_run = run("printf 'a\\377b\\n'; echo after");
+        let mut output = String::new();
+        wait(&mut test_run, &mut output);
+
+        assert_eq!(output, "a\u{fffd}b\nafter\n");
+    }
+
+    #[test]
+    fn test_drop_stops_process_group() {
+        // pytest ignoring SIGINT is killed after the timeout
+        let mut test_run = run("trap '' INT; sleep 30 & echo $!; wait");
+        let mut output = String::new();
+        while output.is_empty() {
+            test_run.poll(&mut output);
+            thread::sleep(Duration::from_millis(10));
+        }
+        let pid: libc::pid_t = output.trim().parse().unwrap();
+        let started = Instant::now();
+        drop(test_run);
+        assert!(started.elapsed() < INTERRUPT_TIMEOUT);
+
+        // SAFETY: signal 0 only checks whether the process exists
+        while unsafe { libc::kill(pid, 0) } == 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "sleep survived"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut test_run = run("trap '' INT; echo started; sleep 30");
+        let mut output = String::new();
+        while output.is_empty() {
+            test_run.poll(&mut output);
+            thread::sleep(Duration::from_millis(10));
+        }
+        // the process ignores SIGINT, so the second cancel has to kill it
+        test_run.cancel();
+        assert!(matches!(test_run.poll(&mut output), RunStatus::Running));
+        test_run.cancel();
+        let status = wait(&mut test_run, &mut output);
+
+        assert!(!status.success());
+        assert!(test_run.is_cancelling());
+    }
+}
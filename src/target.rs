@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+
+use rustpython_parser::ast;
+
+use crate::parametrize::dotted_name;
+
+/// Python the tests are collected for. Module and class level `if` statements on
+/// `sys.version_info` and `sys.platform` are evaluated against it, both branches
+/// are collected when the condition can't be worked out
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Target {
+    /// major and minor version, e.g. `(3, 11)`
+    pub python_version: Option<(u64, u64)>,
+    /// value of `sys.platform`, e.g. `linux` or `win32`
+    pub platform: Option<String>,
+}
+
+impl Target {
+    /// Parses versions like `3.11`
+    pub fn parse_python_version(version: &str) -> Option<(u64, u64)> {
+        let (major, minor) = version.trim().split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    /// Value of the condition, None when it depends on something else than the target
+    pub fn evaluate(&self, condition: &ast::Expr) -> Option<bool> {
+        match &condition.node {
+            ast::ExprKind::BoolOp { op, values } => {
+                // a single known value decides, e.g. a false one in `and`
+                let decisive = matches!(op, ast::Boolop::Or);
+                let mut known = true;
+                for value in values {
+                    match self.evaluate(value) {
+                        Some(value) if value == decisive => return Some(decisive),
+                        Some(_) => {}
+                        None => known = false,
+                    }
+                }
+                known.then_some(!decisive)
+            }
+            ast::ExprKind::UnaryOp {
+                op: ast::Unaryop::Not,
+                operand,
+            } => self.evaluate(operand).map(|value| !value),
+            ast::ExprKind::Compare {
+                left,
+                ops,
+                comparators,
+            } if ops.len() == 1 => self.compare(left, &ops[0], &comparators[0]),
+            ast::ExprKind::Call { func, args, .. } => {
+                let ast::ExprKind::Attribute { value, attr, .. } = &func.node else {
+                    return None;
+                };
+                let platform = self.platform.as_ref()?;
+                if attr != "startswith" || dotted_name(value).as_deref() != Some("sys.platform") {
+                    return None;
+                }
+                match &args.first()?.node {
+                    ast::ExprKind::Constant {
+                        value: ast::Constant::Str(prefix),
+                        ..
+                    } => Some(platform.starts_with(prefix.as_str())),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn compare(&self, left: &ast::Expr, op: &ast::Cmpop, right: &ast::Expr) -> Option<bool> {
+        match dotted_name(left)?.as_str() {
+            "sys.version_info" => {
+                let ordering = compare_version(self.python_version?, &int_tuple(right)?)?;
+                Some(match op {
+                    ast::Cmpop::Eq => ordering == Ordering::Equal,
+                    ast::Cmpop::NotEq => ordering != Ordering::Equal,
+                    ast::Cmpop::Lt => ordering == Ordering::Less,
+                    ast::Cmpop::LtE => ordering != Ordering::Greater,
+                    ast::Cmpop::Gt => ordering == Ordering::Greater,
+                    ast::Cmpop::GtE => ordering != Ordering::Less,
+                    _ => return None,
+                })
+            }
+            "sys.platform" => {
+                let platform = self.platform.as_ref()?;
+                let ast::ExprKind::Constant {
+                    value: ast::Constant::Str(value),
+                    ..
+                } = &right.node
+                else {
+                    return None;
+                };
+                match op {
+                    ast::Cmpop::Eq => Some(platform == value),
+                    ast::Cmpop::NotEq => Some(platform != value),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn int_tuple(expr: &ast::Expr) -> Option<Vec<u64>> {
+    let ast::ExprKind::Tuple { elts, .. } = &expr.node else {
+        return None;
+    };
+    elts.iter()
+        .map(|e| match &e.node {
+            ast::ExprKind::Constant {
+                value: ast::Constant::Int(value),
+                ..
+            } => value.to_string().parse().ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compares `sys.version_info` like python compares tuples. Only major and minor
+/// are known, so comparisons which depend on the micro version are unknown
+fn compare_version((major, minor): (u64, u64), other: &[u64]) -> Option<Ordering> {
+    let version = [major, minor];
+    for (i, part) in other.iter().enumerate() {
+        let ordering = version.get(i)?.cmp(part);
+        if ordering != Ordering::Equal {
+            return Some(ordering);
+        }
+    }
+    // `sys.version_info` has more items than the other tuple
+    Some(Ordering::Greater)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustpython_parser::parse_program;
+
+    #[test]
+    fn test_evaluate() {
+        let target = Target {
+            python_version: Some((3, 11)),
+            platform: Some("linux".to_string()),
+        };
+        let evaluate = |target: &Target, condition: &str| {
+            let program = parse_program(&format!("if {condition}:\n    pass\n"), "<test>").unwrap();
+            let ast::StmtKind::If { test, .. } = &program[0].node else {
+                panic!("not an if statement");
+            };
+            target.evaluate(test)
+        };
+
+        assert_eq!(evaluate(&target, "sys.version_info >= (3, 8)"), Some(true));
+        assert_eq!(evaluate(&target, "sys.version_info < (3, 11)"), Some(false));
+        assert_eq!(
+            evaluate(&target, "sys.version_info == (3, 11)"),
+            Some(false)
+        );
+        assert_eq!(evaluate(&target, "sys.version_info >= (3, 11, 2)"), None);
+        assert_eq!(evaluate(&target, "sys.platform == 'win32'"), Some(false));
+        assert_eq!(
+            evaluate(&target, "not sys.platform.startswith('win')"),
+            Some(true)
+        );
+        assert_eq!(
+            evaluate(&target, "sys.platform == 'linux' and HAS_NUMPY"),
+            None
+        );
+        assert_eq!(
+            evaluate(&target, "sys.platform == 'win32' and HAS_NUMPY"),
+            Some(false)
+        );
+        assert_eq!(
+            evaluate(&target, "HAS_NUMPY or sys.version_info > (3,)"),
+            Some(true)
+        );
+        assert_eq!(
+            evaluate(&Target::default(), "sys.version_info >= (3, 8)"),
+            None
+        );
+    }
+}
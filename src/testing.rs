@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static PROJECTS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Files of a test in a directory of their own, it's removed on drop so that
+/// a failing assert doesn't leave it behind
+pub struct TempProject {
+    /// canonical path, collected paths start with it
+    pub root: PathBuf,
+}
+
+impl TempProject {
+    pub fn new(files: &[(&str, &str)]) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "pytexp_{}_{}",
+            std::process::id(),
+            PROJECTS_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&root).unwrap();
+        let project = TempProject {
+            root: root.canonicalize().unwrap(),
+        };
+        for (path, contents) in files {
+            project.write(path, contents);
+        }
+        project
+    }
+
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn create_dir(&self, path: &str) {
+        fs::create_dir_all(self.root.join(path)).unwrap();
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Path or node id with the root left out, e.g. `api/test_api.py::test_get`
+    pub fn relative(&self, path: impl AsRef<Path>) -> String {
+        let path = path.as_ref().to_string_lossy();
+        let root = format!("{}/", self.root.display());
+        path.strip_prefix(&root).unwrap_or(&path).to_string()
+    }
+}
+
+impl Drop for TempProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
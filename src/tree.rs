@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::entities::ParsedTest;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeKind {
+    Directory,
+    Module,
+    Class,
+    Test,
+}
+
+pub struct TreeNode {
+    /// last segment of the node id, e.g. `TestClient` for `tests/test_api.py::TestClient`
+    pub name: String,
+    /// pytest node id, or the path for directories
+    pub node_id: String,
+    pub kind: NodeKind,
+    pub depth: usize,
+    pub children: Vec<usize>,
+    /// collected test with the same full_path, class rows included
+    pub test: Option<usize>,
+    /// indices of the tests under the node, classes are not counted as tests
+    pub tests: Vec<usize>,
+}
+
+impl TreeNode {
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// Tests grouped by directories, modules and classes
+#[derive(Default)]
+pub struct Tree {
+    pub nodes: Vec<TreeNode>,
+    roots: Vec<usize>,
+}
+
+impl Tree {
+    /// Builds the tree from tests with their indices in the list of collected tests
+    pub fn build<'a>(tests: impl Iterator<Item = (usize, &'a ParsedTest)>) -> Tree {
+        let mut tree = Tree::default();
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for (index, test) in tests {
+            // parameter ids may contain `::` and `/`, they stay with the test name
+            let address = match test.full_path.find('[') {
+                Some(bracket) => &test.full_path[..bracket],
+                None => test.full_path.as_str(),
+            };
+            let (path, names) = match address.split_once("::") {
+                Some((path, names)) => (path, Some(names)),
+                None => (address, None),
+            };
+            let mut segments: Vec<(&str, NodeKind)> = vec![];
+            let path_segments: Vec<&str> = path.split('/').collect();
+            for (i, segment) in path_segments.iter().enumerate() {
+                let kind = match i + 1 == path_segments.len() {
+                    true => NodeKind::Module,
+                    false => NodeKind::Directory,
+                };
+                segments.push((segment, kind));
+            }
+            for name in names.into_iter().flat_map(|names| names.split("::")) {
+                segments.push((name, NodeKind::Test));
+            }
+            if let Some((last, _)) = segments.last_mut() {
+                *last = &test.full_path[address.len() - last.len()..];
+            }
+
+            let mut parent: Option<usize> = None;
+            let mut node_id = String::new();
+            for (depth, (segment, kind)) in segments.into_iter().enumerate() {
+                if depth > 0 {
+                    let separator = match kind {
+                        NodeKind::Test => "::",
+                        _ => "/",
+                    };
+                    node_id.push_str(separator);
+                }
+                node_id.push_str(segment);
+                let node = match ids.get(&node_id) {
+                    Some(node) => *node,
+                    None => {
+                        let node = tree.nodes.len();
+                        tree.nodes.push(TreeNode {
+                            name: segment.to_string(),
+                            node_id: node_id.clone(),
+                            kind,
+                            depth,
+                            children: vec![],
+                            test: None,
+                            tests: vec![],
+                        });
+                        match parent {
+                            Some(parent) => tree.nodes[parent].children.push(node),
+                            None => tree.roots.push(node),
+                        }
+                        ids.insert(node_id.clone(), node);
+                        node
+                    }
+                };
+                parent = Some(node);
+            }
+            if let Some(node) = parent {
+                tree.nodes[node].test = Some(index);
+            }
+        }
+        for root in tree.roots.clone() {
+            tree.collect_tests(root);
+        }
+        tree
+    }
+
+    fn collect_tests(&mut self, node: usize) -> Vec<usize> {
+        let children = self.nodes[node].children.clone();
+        let tests: Vec<usize> = match children.is_empty() {
+            true => self.nodes[node].test.into_iter().collect(),
+            false => {
+                if self.nodes[node].kind == NodeKind::Test {
+                    self.nodes[node].kind = NodeKind::Class;
+                }
+                children
+                    .into_iter()
+                    .flat_map(|child| self.collect_tests(child))
+                    .collect()
+            }
+        };
+        self.nodes[node].tests = tests.clone();
+        tests
+    }
+
+    /// Nodes in display order, children of collapsed nodes are hidden
+    pub fn visible_rows(&self, collapsed: &HashSet<String>) -> Vec<usize> {
+        let mut rows = vec![];
+        let mut stack: Vec<usize> = self.roots.iter().rev().copied().collect();
+        while let Some(node) = stack.pop() {
+            rows.push(node);
+            if !collapsed.contains(&self.nodes[node].node_id) {
+                stack.extend(self.nodes[node].children.iter().rev());
+            }
+        }
+        rows
+    }
+
+    /// Node ids of every node which can be collapsed
+    pub fn inner_node_ids(&self) -> impl Iterator<Item = &str> {
+        self.nodes
+            .iter()
+            .filter(|n| !n.is_leaf())
+            .map(|n| n.node_id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree() {
+        let tests: Vec<ParsedTest> = [
+            ("test_top", "tests/test_a.py"),
+            ("TestClass", "tests/test_a.py"),
+            ("TestClass::test_one[1]", "tests/test_a.py"),
+            ("TestClass::test_one[a::b/c]", "tests/test_a.py"),
+            ("test_b", "tests/unit/test_b.py"),
+        ]
+        .iter()
+        .map(|(name, path)| ParsedTest::new(name.to_string(), 1, path))
+        .collect();
+        let tree = Tree::build(tests.iter().enumerate());
+        let rows = |collapsed: &HashSet<String>| -> Vec<(String, NodeKind, usize)> {
+            tree.visible_rows(collapsed)
+                .into_iter()
+                .map(|n| &tree.nodes[n])
+                .map(|n| (n.name.clone(), n.kind, n.tests.len()))
+                .collect()
+        };
+
+        assert_eq!(
+            rows(&HashSet::new()),
+            vec![
+                ("tests".to_string(), NodeKind::Directory, 4),
+                ("test_a.py".to_string(), NodeKind::Module, 3),
+                ("test_top".to_string(), NodeKind::Test, 1),
+                ("TestClass".to_string(), NodeKind::Class, 2),
+                ("test_one[1]".to_string(), NodeKind::Test, 1),
+                ("test_one[a::b/c]".to_string(), NodeKind::Test, 1),
+                ("unit".to_string(), NodeKind::Directory, 1),
+                ("test_b.py".to_string(), NodeKind::Module, 1),
+                ("test_b".to_string(), NodeKind::Test, 1),
+            ]
+        );
+        let collapsed = HashSet::from(["tests/test_a.py".to_string(), "tests/unit".to_string()]);
+        let names: Vec<String> = rows(&collapsed).into_iter().map(|r| r.0).collect();
+        assert_eq!(names, vec!["tests", "test_a.py", "unit"]);
+        assert_eq!(tree.nodes[3].test, Some(1));
+    }
+}
@@ -1,4 +1,6 @@
 use crate::app::{App, InputMode};
+use crate::entities::{Outcome, Parametrization, ParsedTest};
+use crate::tree::NodeKind;
 use ansi_to_tui::IntoText;
 use std::cmp::min;
 use tui::{
@@ -21,8 +23,27 @@ fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
                 Span::styled("f ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw("| RUN TEST "),
                 Span::styled("Enter ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| RUN FILTERED/FILE/CLASS "),
+                Span::styled("a/F/C ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw("| RUN TEST IN SHELL "),
                 Span::styled("r ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| SELECT/ALL/INVERT/CLEAR "),
+                Span::styled(
+                    "Space/v/i/x ",
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw("| COPY/EXPORT "),
+                Span::styled("y/e ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| TREE/FOLD/FOLD ALL "),
+                Span::styled("t/z/Z ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| HIDE SKIPPED "),
+                Span::styled("s ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| RERUN ON SAVE "),
+                Span::styled("w ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| FIXTURES "),
+                Span::styled("d ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| COLLECTION ERRORS "),
+                Span::styled("E ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw("| NAVIGATE "),
                 Span::styled(
                     "hjkl/arrows PgUp/PgDown/Home/End ",
@@ -31,7 +52,9 @@ fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
                 Span::raw("| ACTIVATE OUTPUT "),
                 Span::styled("2 ", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw("| OPEN FILE "),
-                Span::styled("o", Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled("o ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| CANCEL RUN "),
+                Span::styled("c", Style::default().add_modifier(Modifier::BOLD)),
             ],
             Style::default(),
         ),
@@ -48,6 +71,8 @@ fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
                 ),
                 Span::raw("| ACTIVATE TESTS LIST "),
                 Span::styled("1 ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| CANCEL RUN "),
+                Span::styled("c", Style::default().add_modifier(Modifier::BOLD)),
             ],
             Style::default(),
         ),
@@ -55,6 +80,30 @@ fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
             vec![
                 Span::raw("STOP EDITING "),
                 Span::styled("Esc/Enter ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| SWITCH FILTER MODE "),
+                Span::styled("Tab ", Style::default().add_modifier(Modifier::BOLD)),
+            ],
+            Style::default(),
+        ),
+        InputMode::FixtureScrolling => (
+            vec![
+                Span::raw("CLOSE FIXTURES "),
+                Span::styled("d/Esc/h ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| NAVIGATE "),
+                Span::styled("jk/arrows ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| OPEN DEFINITION "),
+                Span::styled("o/Enter", Style::default().add_modifier(Modifier::BOLD)),
+            ],
+            Style::default(),
+        ),
+        InputMode::CollectionErrorScrolling => (
+            vec![
+                Span::raw("CLOSE ERRORS "),
+                Span::styled("E/Esc/h ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| NAVIGATE "),
+                Span::styled("jk/arrows ", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw("| OPEN FILE "),
+                Span::styled("o/Enter", Style::default().add_modifier(Modifier::BOLD)),
             ],
             Style::default(),
         ),
@@ -68,24 +117,37 @@ fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
     };
     let mut text = Text::from(Spans::from(msg));
     text.patch_style(style);
-    let help_message = Paragraph::new(text);
+    let help_message = Paragraph::new(text).wrap(Wrap { trim: true });
     f.render_widget(help_message, area);
 }
 
 fn draw_filter_input<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let title = match app.filter_error() {
+        Some(error) if !app.input.trim().is_empty() => {
+            format!("Filter ({}, {error})", app.filter_mode.name())
+        }
+        _ => format!("Filter ({})", app.filter_mode.name()),
+    };
     let input = Paragraph::new(app.input.as_ref())
         .style(match app.input_mode {
             InputMode::FilterEditing => Style::default().fg(Color::Yellow),
             _ => Style::default(),
         })
-        .block(Block::default().borders(Borders::ALL).title("Filter"));
-    let count = Paragraph::new(format!("{}/{}", app.filtered_tests_count, app.tests.len()))
+        .block(Block::default().borders(Borders::ALL).title(title.clone()));
+    let mut count = format!("{}/{}", app.filtered_tests_count, app.tests.len());
+    if !app.selection.is_empty() {
+        count = format!("{} selected | {count}", app.selection.len());
+    }
+    if !app.notice.is_empty() {
+        count = format!("{} | {count}", app.notice);
+    }
+    let count = Paragraph::new(count)
         .alignment(tui::layout::Alignment::Right)
         .style(match app.input_mode {
             InputMode::FilterEditing => Style::default().fg(Color::Yellow),
             _ => Style::default(),
         })
-        .block(Block::default().borders(Borders::ALL).title("Filter"));
+        .block(Block::default().borders(Borders::ALL).title(title));
     f.render_widget(input, area);
     f.render_widget(count, area);
     if let InputMode::FilterEditing = app.input_mode {
@@ -93,41 +155,181 @@ fn draw_filter_input<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
     }
 }
 
-fn draw_test_with_output<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
-    let constraints = vec![Constraint::Percentage(50), Constraint::Percentage(50)];
-    let chunks = Layout::default()
-        .constraints(constraints)
-        .direction(Direction::Horizontal)
-        .split(area);
-    let start_task_list = app.test_cursor.saturating_sub(area.height as usize / 2);
-    let filters = app.load_filters_from_app();
-
-    let messages: Vec<ListItem> = app
-        .tests
-        .iter()
-        .filter(|t| App::is_accure_all_filters(&filters.clone(), &t.full_path))
+fn list_rows(app: &App, width: u16, start_task_list: usize, height: usize) -> Vec<ListItem<'_>> {
+    app.filtered_tests()
         .enumerate()
-        .filter(|(i, _)| i >= &start_task_list && i < &(start_task_list + area.height as usize))
+        .filter(|(i, _)| i >= &start_task_list && i < &(start_task_list + height))
         .map(|(i, t)| {
-            let content;
-            let test_line_width = chunks[0].width.saturating_sub(2);  // sub 2 cause of borders
-            if t.full_path.len() > test_line_width.into() {
-                content = t.full_path.chars()
-                    .collect::<Vec<char>>()
-                    .chunks(test_line_width.into())
-                    .map(|c| Spans::from(Span::raw(c.clone().iter().collect::<String>())))
-                    .collect();
-            } else {
-                content = vec![Spans::from(Span::raw(&t.full_path))];
+            // sub 2 cause of borders and 2 more for the status glyph
+            let test_line_width = usize::from(width.saturating_sub(4).max(1));
+            let matched = app
+                .filter_match(&t.full_path)
+                .map(|m| m.positions)
+                .unwrap_or_default();
+            let chars: Vec<char> = t.full_path.chars().collect();
+            let mut content: Vec<Spans> = chars
+                .chunks(test_line_width)
+                .enumerate()
+                .map(|(line, c)| {
+                    let offset = line * test_line_width;
+                    let mut spans = vec![Span::raw("  ")];
+                    spans.extend(highlight(c, offset, &matched));
+                    Spans::from(spans)
+                })
+                .collect();
+            content[0].0[0] = outcome_glyph(app.outcome(t));
+            if let Some(result) = app.results.get(&t.full_path) {
+                content[0].0.push(Span::styled(
+                    format!(" {:.2}s", result.duration.as_secs_f64()),
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+            content[0].0.extend(marker_badges(&t.markers));
+            if let Some(expected) = &t.expected {
+                content.push(Spans::from(Span::styled(
+                    format!("  {expected}"),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::DIM),
+                )));
+            }
+            if t.is_skipped() {
+                for spans in content.iter_mut() {
+                    for span in spans.0.iter_mut() {
+                        span.style = span.style.add_modifier(Modifier::DIM);
+                    }
+                }
+            }
+            if let Some(Parametrization::Unresolved) = t.parametrization {
+                content.push(Spans::from(Span::styled(
+                    format!("  {}", Parametrization::Unresolved),
+                    Style::default().add_modifier(Modifier::DIM),
+                )));
             }
 
             if i == app.test_cursor {
                 ListItem::new(content).style(Style::default().fg(Color::Black).bg(Color::Yellow))
+            } else if app.selection.contains(&t.full_path) {
+                ListItem::new(content).style(Style::default().bg(Color::DarkGray))
             } else {
                 ListItem::new(content)
             }
         })
-        .collect();
+        .collect()
+}
+
+/// `parametrize` is left out, the ids already show it
+fn marker_badges(markers: &[String]) -> Vec<Span<'static>> {
+    markers
+        .iter()
+        .filter(|m| m.as_str() != "parametrize")
+        .flat_map(|m| {
+            [
+                Span::raw(" "),
+                Span::styled(format!("@{m}"), Style::default().fg(Color::Cyan)),
+            ]
+        })
+        .collect()
+}
+
+/// Splits the chars into spans, the ones at matched positions are highlighted
+fn highlight(chars: &[char], offset: usize, matched: &[usize]) -> Vec<Span<'static>> {
+    let style = Style::default()
+        .fg(Color::Magenta)
+        .add_modifier(Modifier::BOLD);
+    let mut spans: Vec<Span> = vec![];
+    let mut current = String::new();
+    let mut current_matched = false;
+    for (i, c) in chars.iter().enumerate() {
+        let is_matched = matched.binary_search(&(offset + i)).is_ok();
+        if is_matched != current_matched && !current.is_empty() {
+            let text = std::mem::take(&mut current);
+            spans.push(match current_matched {
+                true => Span::styled(text, style),
+                false => Span::raw(text),
+            });
+        }
+        current_matched = is_matched;
+        current.push(*c);
+    }
+    spans.push(match current_matched {
+        true => Span::styled(current, style),
+        false => Span::raw(current),
+    });
+    spans
+}
+
+fn tree_rows(app: &App, start_task_list: usize, height: usize) -> Vec<ListItem<'_>> {
+    let tree = app.tree();
+    let rows = tree.visible_rows(&app.collapsed);
+    rows.iter()
+        .enumerate()
+        .skip(start_task_list)
+        .take(height)
+        .map(|(i, node)| {
+            let node = &tree.nodes[*node];
+            let fold_marker = match (node.is_leaf(), app.collapsed.contains(&node.node_id)) {
+                (true, _) => "  ",
+                (false, true) => "▸ ",
+                (false, false) => "▾ ",
+            };
+            let tests: Vec<&ParsedTest> = node.tests.iter().map(|t| &app.tests[*t]).collect();
+            let outcomes: Vec<Outcome> = tests.iter().map(|t| app.outcome(t)).collect();
+            let outcome = outcomes.iter().max().copied().unwrap_or(Outcome::NotRun);
+            let mut spans = vec![
+                Span::raw("  ".repeat(node.depth)),
+                Span::raw(fold_marker),
+                outcome_glyph(outcome),
+                match node.kind {
+                    NodeKind::Directory => Span::raw(format!("{}/", node.name)),
+                    _ => Span::raw(node.name.clone()),
+                },
+            ];
+            let dim = Style::default().add_modifier(Modifier::DIM);
+            if !node.is_leaf() {
+                spans.push(Span::styled(format!(" ({})", tests.len()), dim));
+                for rolled_up in [Outcome::Passed, Outcome::Failed, Outcome::Error] {
+                    let count = outcomes.iter().filter(|o| **o == rolled_up).count();
+                    if count > 0 {
+                        let glyph = outcome_glyph(rolled_up);
+                        spans.push(Span::styled(
+                            format!(" {count}{}", glyph.content.trim_end()),
+                            glyph.style,
+                        ));
+                    }
+                }
+            } else if let Some(result) = app.results.get(&node.node_id) {
+                spans.push(Span::styled(
+                    format!(" {:.2}s", result.duration.as_secs_f64()),
+                    dim,
+                ));
+            }
+
+            let item = ListItem::new(Spans::from(spans));
+            let selected =
+                !tests.is_empty() && tests.iter().all(|t| app.selection.contains(&t.full_path));
+            if i == app.test_cursor {
+                item.style(Style::default().fg(Color::Black).bg(Color::Yellow))
+            } else if selected {
+                item.style(Style::default().bg(Color::DarkGray))
+            } else {
+                item
+            }
+        })
+        .collect()
+}
+
+fn draw_test_with_output<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let constraints = vec![Constraint::Percentage(50), Constraint::Percentage(50)];
+    let chunks = Layout::default()
+        .constraints(constraints)
+        .direction(Direction::Horizontal)
+        .split(area);
+    let start_task_list = app.test_cursor.saturating_sub(area.height as usize / 2);
+    let messages = match app.tree_mode {
+        true => tree_rows(app, start_task_list, area.height as usize),
+        false => list_rows(app, chunks[0].width, start_task_list, area.height as usize),
+    };
     let test_style = match app.input_mode {
         InputMode::TestScrolling => Style::default().fg(Color::Yellow),
         _ => Style::default(),
@@ -140,6 +342,9 @@ fn draw_test_with_output<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
     );
     f.render_widget(messages, chunks[0]);
 
+    if let InputMode::FixtureScrolling = app.input_mode {
+        return draw_fixtures(f, app, chunks[1]);
+    }
     let text = app.test_stdout.clone().into_text().unwrap();
     let start_stdout_list = min(app.stdout_cursor, text.lines.len());
     let stop_stdout_list = min(start_stdout_list + area.height as usize, text.lines.len());
@@ -148,17 +353,134 @@ fn draw_test_with_output<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
         InputMode::OutputScrolling => Style::default().fg(Color::Yellow),
         _ => Style::default(),
     };
+    let title = match &app.test_run {
+        Some(run) if run.is_cancelling() => format!("Output (cancelling {})", run.title()),
+        Some(run) => format!("Output (running {})", run.title()),
+        None if !app.pending_rerun.is_empty() => {
+            format!(
+                "Output (rerun of {} tests pending)",
+                app.pending_rerun.len()
+            )
+        }
+        None if app.auto_rerun => "Output (rerun on save)".to_string(),
+        None => "Output".to_string(),
+    };
     let test_output = Paragraph::new(text_to_show)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(test_style)
-                .title("Output"),
+                .title(title),
         )
         .wrap(Wrap { trim: true });
     f.render_widget(test_output, chunks[1]);
 }
 
+/// Fixture chain of the test under the cursor, with scope, params and definition
+fn draw_fixtures<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chain = app.fixture_chain();
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let start = app
+        .fixture_cursor
+        .saturating_sub(area.height.saturating_sub(2) as usize / 2);
+    let rows: Vec<ListItem> = chain
+        .iter()
+        .enumerate()
+        .skip(start)
+        .map(|(i, fixture)| {
+            let mut spans = vec![
+                Span::raw("  ".repeat(fixture.depth)),
+                Span::raw(fixture.name.clone()),
+            ];
+            match fixture.definition {
+                Some(definition) => {
+                    let parsed = &definition.fixture;
+                    let mut details = vec![parsed.scope.clone()];
+                    if parsed.autouse {
+                        details.push("autouse".to_string());
+                    }
+                    if let Some(params) = &parsed.params {
+                        details.push(format!("params={params}"));
+                    }
+                    spans.push(Span::styled(
+                        format!(" ({})", details.join(", ")),
+                        Style::default().fg(Color::Cyan),
+                    ));
+                    spans.push(Span::styled(
+                        format!("  {}:{}", definition.path, parsed.row_location),
+                        dim,
+                    ));
+                }
+                None if fixture.is_builtin() => spans.push(Span::styled(" (builtin)", dim)),
+                None => spans.push(Span::styled(
+                    " (not found, a plugin?)",
+                    Style::default().fg(Color::Red),
+                )),
+            }
+            let row = ListItem::new(Spans::from(spans));
+            match i == app.fixture_cursor {
+                true => row.style(Style::default().add_modifier(Modifier::REVERSED)),
+                false => row,
+            }
+        })
+        .collect();
+    let fixtures = List::new(rows).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!("Fixtures ({})", chain.len())),
+    );
+    f.render_widget(fixtures, area);
+}
+
+fn outcome_glyph(outcome: Outcome) -> Span<'static> {
+    let (glyph, color) = match outcome {
+        Outcome::NotRun => ("· ", Color::DarkGray),
+        Outcome::Passed => ("✓ ", Color::Green),
+        Outcome::Failed => ("✗ ", Color::Red),
+        Outcome::Error => ("E ", Color::Red),
+        Outcome::Skipped => ("s ", Color::Yellow),
+        Outcome::XFail => ("x ", Color::Yellow),
+        Outcome::XPass => ("X ", Color::Yellow),
+    };
+    Span::styled(glyph, Style::default().fg(color))
+}
+
+/// Errors of the collection, expanded the pane scrolls with its cursor
+fn draw_collection_errors<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let style = Style::default().fg(Color::Red);
+    let expanded = matches!(app.input_mode, InputMode::CollectionErrorScrolling);
+    let visible = area.height.saturating_sub(2) as usize;
+    let start = match expanded {
+        true => app.collection_error_cursor.saturating_sub(visible / 2),
+        false => 0,
+    };
+    let rows: Vec<ListItem> = app
+        .collection_errors
+        .iter()
+        .enumerate()
+        .skip(start)
+        .map(|(i, error)| {
+            let row = ListItem::new(error.to_string());
+            match expanded && i == app.collection_error_cursor {
+                true => row.style(Style::default().add_modifier(Modifier::REVERSED)),
+                false => row,
+            }
+        })
+        .collect();
+    let mut title = format!("Errors ({})", app.collection_errors.len());
+    if !expanded && app.collection_errors.len() > visible {
+        title.push_str(" - E shows all");
+    }
+    let errors = List::new(rows).style(style).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(style)
+            .title(title),
+    );
+    f.render_widget(errors, area);
+}
+
 fn draw_loading<B: Backend>(f: &mut Frame<B>, _: &App, area: Rect) {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -222,7 +544,7 @@ fn draw_error<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
         .collect::<Vec<char>>()
         .chunks(error_width.saturating_sub(1).into())
         .map(|c| c.iter().collect::<String>())
-        .map(|s| Spans::from(s))
+        .map(Spans::from)
         .collect::<Vec<Spans>>();
     let style = Style::default().fg(Color::Red);
     let block = Paragraph::new(lines).block(
@@ -237,22 +559,30 @@ fn draw_error<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
 
 pub fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
     let size = f.size();
+    let mut constraints = vec![
+        Constraint::Length(2),
+        Constraint::Length(3),
+        Constraint::Min(1),
+    ];
+    if let InputMode::CollectionErrorScrolling = app.input_mode {
+        constraints.push(Constraint::Percentage(50));
+    } else if !app.collection_errors.is_empty() {
+        // borders plus one line per error, but never more than a few lines
+        let height = min(app.collection_errors.len(), 5) as u16 + 2;
+        constraints.push(Constraint::Length(height));
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
-        .constraints(
-            [
-                Constraint::Length(1),
-                Constraint::Length(3),
-                Constraint::Min(1),
-            ]
-            .as_ref(),
-        )
+        .constraints(constraints)
         .split(size);
 
     draw_help(f, app, chunks[0]);
     draw_filter_input(f, app, chunks[1]);
     draw_test_with_output(f, app, chunks[2]);
+    if !app.collection_errors.is_empty() {
+        draw_collection_errors(f, app, chunks[3]);
+    }
     if app.loading_lock {
         draw_loading(f, app, size);
     }
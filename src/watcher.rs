@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use anyhow::Result;
+use notify::event::CreateKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::config::Config;
+use crate::parser;
+
+/// Watches the directories collection looks into for changes of python files.
+/// Every directory is watched on its own, so that `norecursedirs` like virtual
+/// envs don't use up the inotify watches
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    invocation_dir: PathBuf,
+}
+
+impl Watcher {
+    pub fn new(config: &Config, roots: &[PathBuf]) -> Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = Watcher {
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            invocation_dir: std::env::current_dir()?,
+        };
+        for root in roots {
+            watcher.watch_dirs(config, root)?;
+        }
+        Ok(watcher)
+    }
+
+    fn watch_dirs(&mut self, config: &Config, root: &Path) -> Result<()> {
+        for entry in parser::walk(config, root).filter(|e| e.file_type().is_dir()) {
+            self.watcher
+                .watch(entry.path(), RecursiveMode::NonRecursive)?;
+        }
+        Ok(())
+    }
+
+    /// Python files created, modified or removed since the last call, relative to
+    /// the invocation dir like the collected paths. New directories are watched too
+    pub fn changed_files(&mut self, config: &Config) -> Vec<PathBuf> {
+        let events: Vec<Event> = self.events.try_iter().filter_map(|e| e.ok()).collect();
+        let mut changed = vec![];
+        for event in events {
+            if let EventKind::Access(_) = event.kind {
+                continue;
+            }
+            for path in event.paths {
+                if let EventKind::Create(CreateKind::Folder) = event.kind {
+                    if !config.is_norecursedir(&path) && !parser::is_skipped_dir(&path) {
+                        // the directory might be gone already, it's fine to miss it
+                        let _ = self.watch_dirs(config, &path);
+                    }
+                    continue;
+                }
+                if path.extension().map_or(true, |ext| ext != "py") {
+                    continue;
+                }
+                let path = path.strip_prefix(&self.invocation_dir).unwrap_or(&path);
+                let path = path.strip_prefix(".").unwrap_or(path).to_path_buf();
+                if !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed.sort();
+        changed
+    }
+}